@@ -0,0 +1,453 @@
+use alloy::primitives::{Address, Log, I256, U256};
+use alloy::sol;
+use alloy::sol_types::SolEvent;
+use foundry_evm::traces::{CallKind, CallTraceArena};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+sol! {
+    event Transfer(address indexed from, address indexed to, uint256 value);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+    event ApprovalForAll(address indexed owner, address indexed operator, bool approved);
+    event TransferSingle(
+        address indexed operator,
+        address indexed from,
+        address indexed to,
+        uint256 id,
+        uint256 value
+    );
+    event TransferBatch(
+        address indexed operator,
+        address indexed from,
+        address indexed to,
+        uint256[] ids,
+        uint256[] values
+    );
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetStandard {
+    Native,
+    Erc20,
+    Erc721,
+    Erc1155,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetChanges {
+    pub transfers: Vec<AssetTransfer>,
+    pub approvals: Vec<AssetApproval>,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetTransfer {
+    pub standard: AssetStandard,
+    /// The token contract, `None` for native ETH.
+    pub token: Option<Address>,
+    pub from: Address,
+    pub to: Address,
+    pub amount: U256,
+    pub token_id: Option<U256>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetApproval {
+    /// `None` for `ApprovalForAll`, which ERC-721 and ERC-1155 share.
+    pub standard: Option<AssetStandard>,
+    pub token: Address,
+    pub owner: Address,
+    pub spender: Address,
+    pub amount: Option<U256>,
+    pub token_id: Option<U256>,
+    pub approved: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceChange {
+    pub address: Address,
+    pub standard: AssetStandard,
+    pub token: Option<Address>,
+    pub token_id: Option<U256>,
+    pub delta: I256,
+}
+
+/// Collects the asset movements of an execution: token events from `logs`, and native value
+/// transfers from the calls in `trace` that were not reverted.
+pub fn asset_changes(logs: &[Log], trace: Option<&CallTraceArena>) -> AssetChanges {
+    let mut changes = AssetChanges::default();
+
+    if let Some(trace) = trace {
+        changes.transfers.extend(native_transfers(trace));
+    }
+
+    for log in logs {
+        decode_log(log, &mut changes);
+    }
+
+    changes.balance_changes = balance_changes(&changes.transfers);
+    changes
+}
+
+fn native_transfers(trace: &CallTraceArena) -> Vec<AssetTransfer> {
+    let nodes = trace.nodes();
+    let mut reverted = vec![false; nodes.len()];
+    let mut transfers = Vec::new();
+
+    // Parents are always recorded before their children, so a single pass is enough to
+    // propagate reverts down to the calls they undo.
+    for node in nodes {
+        reverted[node.idx] =
+            !node.trace.success || node.parent.map(|parent| reverted[parent]).unwrap_or(false);
+
+        if reverted[node.idx]
+            || node.trace.value.is_zero()
+            || matches!(node.trace.kind, CallKind::DelegateCall | CallKind::CallCode)
+        {
+            continue;
+        }
+
+        transfers.push(AssetTransfer {
+            standard: AssetStandard::Native,
+            token: None,
+            from: node.trace.caller,
+            to: node.trace.address,
+            amount: node.trace.value,
+            token_id: None,
+        });
+    }
+
+    transfers
+}
+
+fn decode_log(log: &Log, changes: &mut AssetChanges) {
+    let topics = log.data.topics();
+    let Some(signature) = topics.first() else {
+        return;
+    };
+
+    // ERC-20 and ERC-721 share the `Transfer` and `Approval` signatures, they differ only
+    // in whether the last argument is indexed.
+    if *signature == Transfer::SIGNATURE_HASH && topics.len() >= 3 {
+        let (from, to) = (Address::from_word(topics[1]), Address::from_word(topics[2]));
+        match (topics.len(), word(&log.data.data)) {
+            (3, Some(amount)) => changes.transfers.push(AssetTransfer {
+                standard: AssetStandard::Erc20,
+                token: Some(log.address),
+                from,
+                to,
+                amount,
+                token_id: None,
+            }),
+            (4, _) => changes.transfers.push(AssetTransfer {
+                standard: AssetStandard::Erc721,
+                token: Some(log.address),
+                from,
+                to,
+                amount: U256::from(1),
+                token_id: Some(U256::from_be_bytes(topics[3].0)),
+            }),
+            _ => {}
+        }
+    } else if *signature == Approval::SIGNATURE_HASH && topics.len() >= 3 {
+        let (owner, spender) = (Address::from_word(topics[1]), Address::from_word(topics[2]));
+        match (topics.len(), word(&log.data.data)) {
+            (3, Some(amount)) => changes.approvals.push(AssetApproval {
+                standard: Some(AssetStandard::Erc20),
+                token: log.address,
+                owner,
+                spender,
+                amount: Some(amount),
+                token_id: None,
+                approved: None,
+            }),
+            (4, _) => changes.approvals.push(AssetApproval {
+                standard: Some(AssetStandard::Erc721),
+                token: log.address,
+                owner,
+                spender,
+                amount: None,
+                token_id: Some(U256::from_be_bytes(topics[3].0)),
+                approved: None,
+            }),
+            _ => {}
+        }
+    } else if let Ok(event) = ApprovalForAll::decode_log_data(&log.data, true) {
+        changes.approvals.push(AssetApproval {
+            standard: None,
+            token: log.address,
+            owner: event.owner,
+            spender: event.operator,
+            amount: None,
+            token_id: None,
+            approved: Some(event.approved),
+        });
+    } else if let Ok(event) = TransferSingle::decode_log_data(&log.data, true) {
+        changes.transfers.push(AssetTransfer {
+            standard: AssetStandard::Erc1155,
+            token: Some(log.address),
+            from: event.from,
+            to: event.to,
+            amount: event.value,
+            token_id: Some(event.id),
+        });
+    } else if let Ok(event) = TransferBatch::decode_log_data(&log.data, true) {
+        for (id, value) in event.ids.into_iter().zip(event.values) {
+            changes.transfers.push(AssetTransfer {
+                standard: AssetStandard::Erc1155,
+                token: Some(log.address),
+                from: event.from,
+                to: event.to,
+                amount: value,
+                token_id: Some(id),
+            });
+        }
+    }
+}
+
+fn balance_changes(transfers: &[AssetTransfer]) -> Vec<BalanceChange> {
+    // (inflow, outflow) per holder and asset
+    let mut flows: BTreeMap<_, (U256, U256)> = BTreeMap::new();
+
+    for transfer in transfers {
+        let asset = (transfer.standard, transfer.token, transfer.token_id);
+
+        // The zero address is the mint source and burn sink, not a holder
+        if !transfer.from.is_zero() {
+            let (_, outflow) = flows.entry((transfer.from, asset)).or_default();
+            *outflow = outflow.saturating_add(transfer.amount);
+        }
+        if !transfer.to.is_zero() {
+            let (inflow, _) = flows.entry((transfer.to, asset)).or_default();
+            *inflow = inflow.saturating_add(transfer.amount);
+        }
+    }
+
+    flows
+        .into_iter()
+        .filter(|(_, (inflow, outflow))| inflow != outflow)
+        .map(
+            |((address, (standard, token, token_id)), (inflow, outflow))| {
+                let delta = if inflow > outflow {
+                    I256::try_from(inflow - outflow).unwrap_or(I256::MAX)
+                } else {
+                    I256::try_from(outflow - inflow)
+                        .map(|delta| -delta)
+                        .unwrap_or(I256::MIN)
+                };
+
+                BalanceChange {
+                    address,
+                    standard,
+                    token,
+                    token_id,
+                    delta,
+                }
+            },
+        )
+        .collect()
+}
+
+fn word(data: &[u8]) -> Option<U256> {
+    (data.len() == 32).then(|| U256::from_be_slice(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, B256};
+
+    const TOKEN: Address = address!("00000000000000000000000000000000000000aa");
+    const ALICE: Address = address!("0000000000000000000000000000000000000a11");
+    const BOB: Address = address!("0000000000000000000000000000000000000b0b");
+
+    fn log<E: SolEvent>(event: &E) -> Log {
+        Log {
+            address: TOKEN,
+            data: event.encode_log_data(),
+        }
+    }
+
+    fn delta(changes: &AssetChanges, address: Address) -> Option<I256> {
+        changes
+            .balance_changes
+            .iter()
+            .find(|change| change.address == address)
+            .map(|change| change.delta)
+    }
+
+    #[test]
+    fn decodes_erc20_transfers() {
+        let changes = asset_changes(
+            &[log(&Transfer {
+                from: ALICE,
+                to: BOB,
+                value: U256::from(100),
+            })],
+            None,
+        );
+
+        assert_eq!(
+            changes.transfers,
+            vec![AssetTransfer {
+                standard: AssetStandard::Erc20,
+                token: Some(TOKEN),
+                from: ALICE,
+                to: BOB,
+                amount: U256::from(100),
+                token_id: None,
+            }]
+        );
+        assert_eq!(delta(&changes, ALICE), Some(I256::try_from(-100).unwrap()));
+        assert_eq!(delta(&changes, BOB), Some(I256::try_from(100).unwrap()));
+    }
+
+    #[test]
+    fn decodes_erc721_transfers_by_their_indexed_token_id() {
+        let token_id = U256::from(7);
+        let log = Log::new_unchecked(
+            TOKEN,
+            vec![
+                Transfer::SIGNATURE_HASH,
+                ALICE.into_word(),
+                BOB.into_word(),
+                B256::from(token_id.to_be_bytes()),
+            ],
+            Default::default(),
+        );
+
+        let changes = asset_changes(&[log], None);
+
+        assert_eq!(changes.transfers.len(), 1);
+        assert_eq!(changes.transfers[0].standard, AssetStandard::Erc721);
+        assert_eq!(changes.transfers[0].amount, U256::from(1));
+        assert_eq!(changes.transfers[0].token_id, Some(token_id));
+        assert_eq!(delta(&changes, BOB), Some(I256::ONE));
+    }
+
+    #[test]
+    fn decodes_erc1155_single_and_batch_transfers() {
+        let changes = asset_changes(
+            &[
+                log(&TransferSingle {
+                    operator: ALICE,
+                    from: ALICE,
+                    to: BOB,
+                    id: U256::from(1),
+                    value: U256::from(5),
+                }),
+                log(&TransferBatch {
+                    operator: ALICE,
+                    from: ALICE,
+                    to: BOB,
+                    ids: vec![U256::from(1), U256::from(2)],
+                    values: vec![U256::from(3), U256::from(4)],
+                }),
+            ],
+            None,
+        );
+
+        assert_eq!(changes.transfers.len(), 3);
+        assert!(changes
+            .transfers
+            .iter()
+            .all(|transfer| transfer.standard == AssetStandard::Erc1155));
+
+        let bob_balances: Vec<_> = changes
+            .balance_changes
+            .iter()
+            .filter(|change| change.address == BOB)
+            .map(|change| (change.token_id, change.delta))
+            .collect();
+        assert_eq!(
+            bob_balances,
+            vec![
+                (Some(U256::from(1)), I256::try_from(8).unwrap()),
+                (Some(U256::from(2)), I256::try_from(4).unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_approvals() {
+        let changes = asset_changes(
+            &[
+                log(&Approval {
+                    owner: ALICE,
+                    spender: BOB,
+                    value: U256::MAX,
+                }),
+                log(&ApprovalForAll {
+                    owner: ALICE,
+                    operator: BOB,
+                    approved: true,
+                }),
+            ],
+            None,
+        );
+
+        assert_eq!(changes.approvals.len(), 2);
+        assert_eq!(changes.approvals[0].standard, Some(AssetStandard::Erc20));
+        assert_eq!(changes.approvals[0].amount, Some(U256::MAX));
+        assert_eq!(changes.approvals[1].standard, None);
+        assert_eq!(changes.approvals[1].approved, Some(true));
+        assert!(changes.balance_changes.is_empty());
+    }
+
+    #[test]
+    fn nets_transfers_and_leaves_out_the_zero_address() {
+        let changes = asset_changes(
+            &[
+                // Minted to Alice, sent to Bob and partly back
+                log(&Transfer {
+                    from: Address::ZERO,
+                    to: ALICE,
+                    value: U256::from(10),
+                }),
+                log(&Transfer {
+                    from: ALICE,
+                    to: BOB,
+                    value: U256::from(10),
+                }),
+                log(&Transfer {
+                    from: BOB,
+                    to: ALICE,
+                    value: U256::from(4),
+                }),
+            ],
+            None,
+        );
+
+        assert_eq!(changes.transfers.len(), 3);
+        assert_eq!(delta(&changes, ALICE), Some(I256::try_from(4).unwrap()));
+        assert_eq!(delta(&changes, BOB), Some(I256::try_from(6).unwrap()));
+        assert_eq!(delta(&changes, Address::ZERO), None);
+    }
+
+    #[test]
+    fn leaves_out_balances_that_net_to_zero() {
+        let changes = asset_changes(
+            &[
+                log(&Transfer {
+                    from: ALICE,
+                    to: BOB,
+                    value: U256::from(1),
+                }),
+                log(&Transfer {
+                    from: BOB,
+                    to: ALICE,
+                    value: U256::from(1),
+                }),
+            ],
+            None,
+        );
+
+        assert_eq!(changes.transfers.len(), 2);
+        assert!(changes.balance_changes.is_empty());
+    }
+}
@@ -1,4 +1,15 @@
-use clap::Parser;
+use alloy::json_abi::JsonAbi;
+use alloy::primitives::Address;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::errors::NoURLForChainIdError;
+use crate::evm::{DecoderConfig, DEFAULT_BLOCK_TIME_MS};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -7,36 +18,338 @@ struct Args {
     #[arg(long)]
     port: u16,
 
+    /// RPC URL used for chains without their own entry in --chain-fork-url or the config file
     #[arg(long)]
-    fork_url: String,
+    fork_url: Option<String>,
+
+    /// RPC URL for a specific chain, as CHAIN_ID=URL. Can be repeated
+    #[arg(long, value_parser = parse_chain_value::<String>)]
+    chain_fork_url: Vec<(u64, String)>,
+
+    /// Milliseconds between blocks for chains without a configured or well-known block time
+    #[arg(long, default_value_t = DEFAULT_BLOCK_TIME_MS)]
+    block_time_ms: u64,
+
+    /// Milliseconds between blocks on a specific chain, as CHAIN_ID=MS. Can be repeated
+    #[arg(long, value_parser = parse_chain_value::<u64>)]
+    chain_block_time_ms: Vec<(u64, u64)>,
+
+    /// JSON config file, e.g. {"forkUrls": {"1": "https://..."}, "blockTimesMs": {"10": 2000},
+    /// "labels": {"1": {"0x...": "Vault"}}, "abis": {"1": [[...]]}}
+    #[arg(long)]
+    config: Option<PathBuf>,
 
     #[arg(long)]
     etherscan_key: Option<String>,
 
+    /// ABIs to decode traces and logs with on every chain: a JSON ABI, a foundry artifact, or a
+    /// directory of foundry artifacts such as a project's `out` directory
+    #[arg(long)]
+    abis: Option<PathBuf>,
+
+    /// Directory to cache looked up signatures in, defaults to foundry's cache directory
+    #[arg(long)]
+    signatures_cache_dir: Option<PathBuf>,
+
+    /// Never look up contracts on Etherscan or signatures online, only decode with local ABIs
+    /// and cached signatures
+    #[arg(long)]
+    offline: bool,
+
     #[arg(long)]
     api_key: Option<String>,
 
     #[arg(long)]
     max_request_size: Option<u64>,
+
+    /// Seconds a stateful simulation may sit unused before it is removed
+    #[arg(long, default_value_t = 900)]
+    stateful_simulation_ttl: u64,
+
+    /// Maximum number of stateful simulations alive at the same time
+    #[arg(long)]
+    max_stateful_simulations: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigFile {
+    #[serde(default)]
+    fork_urls: HashMap<u64, String>,
+    #[serde(default)]
+    block_times_ms: HashMap<u64, u64>,
+    /// ABIs to decode traces, logs and reverts with, by chain id
+    #[serde(default)]
+    abis: HashMap<u64, Vec<JsonAbi>>,
+    /// Names to show contracts under in traces and logs, by chain id and contract address
+    #[serde(default)]
+    labels: HashMap<u64, HashMap<Address, String>>,
 }
 
+/// A JSON file passed to `--abis`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AbiFile {
+    Artifact { abi: JsonAbi },
+    Abi(JsonAbi),
+}
+
+impl AbiFile {
+    fn into_abi(self) -> JsonAbi {
+        match self {
+            AbiFile::Artifact { abi } | AbiFile::Abi(abi) => abi,
+        }
+    }
+}
+
+/// Block times of well-known chains, used unless configured otherwise.
+const DEFAULT_BLOCK_TIMES_MS: [(u64, u64); 6] = [
+    (1, 12_000),
+    (10, 2_000),
+    (137, 2_000),
+    (8453, 2_000),
+    (42161, 250),
+    (11155111, 12_000),
+];
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub port: u16,
-    pub fork_url: String,
-    pub etherscan_key: Option<String>,
+    pub fork_url: Option<String>,
+    pub fork_urls: HashMap<u64, String>,
+    pub block_time_ms: u64,
+    pub block_times_ms: HashMap<u64, u64>,
+    pub abis: Arc<HashMap<u64, Vec<JsonAbi>>>,
+    pub labels: Arc<HashMap<u64, HashMap<Address, String>>>,
+    /// Decodes with the ABIs from `--abis` on every chain
+    pub decoding: Arc<DecoderConfig>,
     pub api_key: Option<String>,
     pub max_request_size: u64,
+    pub stateful_simulation_ttl: u64,
+    pub max_stateful_simulations: Option<usize>,
+}
+
+impl Config {
+    /// The RPC URL to fork `chain_id` from, falling back to the default fork URL.
+    pub fn fork_url_for(&self, chain_id: u64) -> Result<String, NoURLForChainIdError> {
+        self.fork_urls
+            .get(&chain_id)
+            .or(self.fork_url.as_ref())
+            .cloned()
+            .ok_or(NoURLForChainIdError)
+    }
+
+    /// The milliseconds between blocks on `chain_id`, falling back to the default block time.
+    pub fn block_time_ms_for(&self, chain_id: u64) -> u64 {
+        self.block_times_ms
+            .get(&chain_id)
+            .copied()
+            .unwrap_or(self.block_time_ms)
+    }
 }
 
 pub fn config() -> Config {
     let args = Args::parse();
 
+    let config_file = match &args.config {
+        Some(path) => read_config_file(path).unwrap_or_else(|err| {
+            Args::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("invalid config file {}: {err}", path.display()),
+                )
+                .exit()
+        }),
+        None => ConfigFile::default(),
+    };
+
+    let local_abis = match &args.abis {
+        Some(path) => read_abis(path).unwrap_or_else(|err| {
+            Args::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("invalid ABIs {}: {err}", path.display()),
+                )
+                .exit()
+        }),
+        None => Vec::new(),
+    };
+
+    // Command line URLs take precedence over the ones from the config file
+    let mut fork_urls = config_file.fork_urls;
+    fork_urls.extend(args.chain_fork_url);
+
+    let mut block_times_ms: HashMap<u64, u64> = DEFAULT_BLOCK_TIMES_MS.into_iter().collect();
+    block_times_ms.extend(config_file.block_times_ms);
+    block_times_ms.extend(args.chain_block_time_ms);
+
+    if args.fork_url.is_none() && fork_urls.is_empty() {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "no fork URL configured, use --fork-url, --chain-fork-url or a config file",
+            )
+            .exit();
+    }
+
     Config {
         port: args.port,
         fork_url: args.fork_url,
-        etherscan_key: args.etherscan_key,
+        fork_urls,
+        block_time_ms: args.block_time_ms,
+        block_times_ms,
+        abis: Arc::new(config_file.abis),
+        labels: Arc::new(config_file.labels),
+        decoding: Arc::new(DecoderConfig::new(
+            &local_abis,
+            args.etherscan_key,
+            args.signatures_cache_dir,
+            args.offline,
+        )),
         api_key: args.api_key,
         max_request_size: args.max_request_size.unwrap_or(16) * 1024,
+        stateful_simulation_ttl: args.stateful_simulation_ttl,
+        max_stateful_simulations: args.max_stateful_simulations,
+    }
+}
+
+fn read_config_file(path: &Path) -> eyre::Result<ConfigFile> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Reads the ABI of a single JSON file, or of every foundry artifact in a directory. Other JSON
+/// files in the directory are skipped.
+fn read_abis(path: &Path) -> eyre::Result<Vec<JsonAbi>> {
+    if !path.is_dir() {
+        let contents = std::fs::read_to_string(path)?;
+        let abi_file: AbiFile = serde_json::from_str(&contents)?;
+        return Ok(vec![abi_file.into_abi()]);
+    }
+
+    let mut abis = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            // Build info holds whole compiler outputs, large and without a top level ABI
+            if path.file_name().is_some_and(|name| name == "build-info") {
+                continue;
+            }
+            abis.extend(read_abis(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let contents = std::fs::read_to_string(&path)?;
+            if let Ok(AbiFile::Artifact { abi }) = serde_json::from_str(&contents) {
+                abis.push(abi);
+            }
+        }
+    }
+
+    Ok(abis)
+}
+
+fn parse_chain_value<T>(value: &str) -> Result<(u64, T), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let (chain_id, chain_value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected CHAIN_ID=VALUE, got {value}"))?;
+    let chain_id = chain_id
+        .parse()
+        .map_err(|err| format!("invalid chain id {chain_id}: {err}"))?;
+    let chain_value = chain_value
+        .parse()
+        .map_err(|err| format!("invalid value {chain_value}: {err}"))?;
+
+    Ok((chain_id, chain_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABI: &str = r#"[{"type":"function","name":"deposit","inputs":[],"outputs":[],"stateMutability":"payable"}]"#;
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("simulatoor-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, path: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn function_names(abis: &[JsonAbi]) -> Vec<&str> {
+        abis.iter()
+            .flat_map(JsonAbi::functions)
+            .map(|function| function.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_chain_values() {
+        assert_eq!(
+            parse_chain_value::<String>("1=https://eth.example=1"),
+            Ok((1, "https://eth.example=1".to_string()))
+        );
+        assert_eq!(parse_chain_value::<u64>("10=2000"), Ok((10, 2000)));
+    }
+
+    #[test]
+    fn rejects_invalid_chain_values() {
+        assert_eq!(
+            parse_chain_value::<u64>("2000"),
+            Err("expected CHAIN_ID=VALUE, got 2000".to_string())
+        );
+        assert!(parse_chain_value::<u64>("mainnet=2000")
+            .unwrap_err()
+            .starts_with("invalid chain id mainnet"));
+        assert!(parse_chain_value::<u64>("1=fast")
+            .unwrap_err()
+            .starts_with("invalid value fast"));
+    }
+
+    #[test]
+    fn reads_a_single_abi_or_artifact() {
+        let dir = TempDir::new();
+        let abi = dir.write("Vault.abi.json", ABI);
+        let artifact = dir.write("Vault.json", &format!(r#"{{"abi": {ABI}}}"#));
+
+        assert_eq!(function_names(&read_abis(&abi).unwrap()), ["deposit"]);
+        assert_eq!(function_names(&read_abis(&artifact).unwrap()), ["deposit"]);
+    }
+
+    #[test]
+    fn reads_artifacts_recursively_and_skips_build_info() {
+        let dir = TempDir::new();
+        dir.write("Vault.sol/Vault.json", &format!(r#"{{"abi": {ABI}}}"#));
+        dir.write(
+            "build-info/0123.json",
+            &format!(r#"{{"abi": {ABI}, "output": {{}}}}"#),
+        );
+        // Only artifacts are read from directories, and only JSON files
+        dir.write("Vault.sol/Vault.abi.json", ABI);
+        dir.write("Vault.sol/Vault.txt", &format!(r#"{{"abi": {ABI}}}"#));
+
+        assert_eq!(function_names(&read_abis(&dir.0).unwrap()), ["deposit"]);
     }
 }
@@ -0,0 +1,330 @@
+use alloy::dyn_abi::{DynSolValue, EventExt, JsonAbiExt};
+use alloy::json_abi::Event;
+use alloy::primitives::{hex, Address, Log, Selector, U256};
+use alloy::sol_types::{Panic, Revert, SolError};
+use foundry_evm::traces::identifier::SingleSignaturesIdentifier;
+use foundry_evm::traces::{CallTraceArena, CallTraceDecoder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RevertKind {
+    /// `Error(string)`, from `require` and `revert` with a message
+    Error,
+    /// `Panic(uint256)`, from failed asserts, overflows and the like
+    Panic,
+    Custom,
+    /// Empty, unknown or undecodable revert data
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RevertInfo {
+    pub kind: RevertKind,
+    /// The message of `Error(string)`, the meaning of a panic code, or the revert formatted
+    /// with what is known about it otherwise
+    pub reason: String,
+    pub panic_code: Option<U256>,
+    /// Signature of the custom error, e.g. `InsufficientBalance(uint256,uint256)`
+    pub error: Option<String>,
+    pub args: Option<Vec<Value>>,
+    /// The contract whose call frame the revert originated in
+    pub address: Option<Address>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedLog {
+    /// Index of the log in `logs`
+    pub log_index: usize,
+    pub address: Address,
+    pub label: Option<String>,
+    pub name: String,
+    /// e.g. `Transfer(address,address,uint256)`
+    pub signature: String,
+    pub params: Vec<DecodedParam>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub indexed: bool,
+    pub value: Value,
+}
+
+/// Decodes revert `data` into a [`RevertInfo`], falling back to `reason` for anything it can't
+/// decode. Custom errors are looked up in the errors known to `decoder`, from the ABIs of
+/// identified and user-supplied contracts, or else with `signatures`.
+pub async fn decode_revert(
+    data: &[u8],
+    reason: String,
+    decoder: &CallTraceDecoder,
+    signatures: Option<&SingleSignaturesIdentifier>,
+    trace: Option<&CallTraceArena>,
+) -> RevertInfo {
+    let mut revert = RevertInfo {
+        kind: RevertKind::Unknown,
+        reason,
+        panic_code: None,
+        error: None,
+        args: None,
+        address: trace.and_then(revert_origin),
+    };
+
+    if data.starts_with(&Revert::SELECTOR) {
+        if let Ok(error) = Revert::abi_decode(data, false) {
+            revert.kind = RevertKind::Error;
+            revert.reason = error.reason;
+        }
+    } else if data.starts_with(&Panic::SELECTOR) {
+        if let Ok(panic) = Panic::abi_decode(data, false) {
+            revert.kind = RevertKind::Panic;
+            revert.reason = match panic.kind() {
+                Some(kind) => kind.as_str().to_string(),
+                None => "unknown panic code".to_string(),
+            };
+            revert.panic_code = Some(panic.code);
+        }
+    } else if let Some(selector) = data.get(..4) {
+        let mut errors = decoder
+            .revert_decoder
+            .errors
+            .get(&Selector::from_slice(selector))
+            .cloned()
+            .unwrap_or_default();
+        if errors.is_empty() {
+            if let Some(signatures) = signatures {
+                errors.extend(signatures.write().await.identify_error(selector).await);
+            }
+        }
+
+        if let Some((error, args)) = errors.iter().find_map(|error| {
+            error
+                .abi_decode_input(&data[4..], false)
+                .ok()
+                .map(|args| (error, args))
+        }) {
+            revert.kind = RevertKind::Custom;
+            revert.error = Some(error.signature());
+            revert.args = Some(args.iter().map(to_json).collect());
+        }
+    }
+
+    revert
+}
+
+/// Decodes the `logs` whose events are known to `decoder`, from the ABIs of identified
+/// contracts, or else can be found with `signatures`. Logs that can't be decoded are left out.
+pub async fn decode_logs(
+    logs: &[Log],
+    decoder: &CallTraceDecoder,
+    signatures: Option<&SingleSignaturesIdentifier>,
+) -> Vec<DecodedLog> {
+    let mut decoded_logs = Vec::new();
+    for (log_index, log) in logs.iter().enumerate() {
+        let Some(selector) = log.topics().first() else {
+            continue;
+        };
+
+        let mut events = decoder
+            .events
+            .get(&(*selector, log.topics().len() - 1))
+            .cloned()
+            .unwrap_or_default();
+        if events.is_empty() {
+            if let Some(signatures) = signatures {
+                events.extend(signatures.write().await.identify_event(&selector[..]).await);
+            }
+        }
+
+        if let Some((event, params)) = events
+            .iter()
+            .find_map(|event| decode_params(event, log).map(|params| (event, params)))
+        {
+            decoded_logs.push(DecodedLog {
+                log_index,
+                address: log.address,
+                label: decoder.labels.get(&log.address).cloned(),
+                name: event.name.clone(),
+                signature: event.signature(),
+                params,
+            });
+        }
+    }
+
+    decoded_logs
+}
+
+fn decode_params(event: &Event, log: &Log) -> Option<Vec<DecodedParam>> {
+    let decoded = event.decode_log(&log.data, false).ok()?;
+    let (mut indexed, mut body) = (decoded.indexed.iter(), decoded.body.iter());
+
+    event
+        .inputs
+        .iter()
+        .map(|input| {
+            let value = if input.indexed {
+                indexed.next()
+            } else {
+                body.next()
+            }?;
+
+            Some(DecodedParam {
+                name: input.name.clone(),
+                ty: input.ty.clone(),
+                indexed: input.indexed,
+                value: to_json(value),
+            })
+        })
+        .collect()
+}
+
+/// Follows the revert down the call tree, through the failed calls that returned the same
+/// data as their parent, to the call it was first raised in.
+fn revert_origin(trace: &CallTraceArena) -> Option<Address> {
+    let nodes = trace.nodes();
+    let mut node = nodes.first()?;
+    while let Some(child) = node
+        .children
+        .iter()
+        .rev()
+        .map(|&child| &nodes[child])
+        .find(|child| !child.trace.success && child.trace.output == node.trace.output)
+    {
+        node = child;
+    }
+
+    Some(node.trace.address)
+}
+
+/// Formats a decoded ABI value as JSON. Integers are decimal strings since they may not fit
+/// in a JSON number, bytes are hex strings.
+pub fn to_json(value: &DynSolValue) -> Value {
+    if let Some(address) = value.as_address() {
+        Value::String(address.to_checksum(None))
+    } else if let Some(boolean) = value.as_bool() {
+        Value::Bool(boolean)
+    } else if let Some((int, _)) = value.as_int() {
+        Value::String(int.to_string())
+    } else if let Some((uint, _)) = value.as_uint() {
+        Value::String(uint.to_string())
+    } else if let Some((word, size)) = value.as_fixed_bytes() {
+        Value::String(hex::encode_prefixed(&word[..size]))
+    } else if let Some(bytes) = value.as_bytes() {
+        Value::String(hex::encode_prefixed(bytes))
+    } else if let Some(string) = value.as_str() {
+        Value::String(string.to_string())
+    } else if let Some(values) = value
+        .as_array()
+        .or_else(|| value.as_fixed_array())
+        .or_else(|| value.as_tuple())
+    {
+        Value::Array(values.iter().map(to_json).collect())
+    } else {
+        Value::Null
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::json_abi::Error;
+    use alloy::sol;
+    use alloy::sol_types::PanicKind;
+    use foundry_evm::traces::CallTraceDecoderBuilder;
+
+    sol! {
+        error InsufficientBalance(uint256 available, uint256 required);
+    }
+
+    async fn decode(data: &[u8], decoder: &CallTraceDecoder) -> RevertInfo {
+        decode_revert(data, "execution reverted".to_string(), decoder, None, None).await
+    }
+
+    #[tokio::test]
+    async fn decodes_error_strings() {
+        let data = Revert::from("not the owner".to_string()).abi_encode();
+
+        let revert = decode(&data, &CallTraceDecoderBuilder::new().build()).await;
+
+        assert_eq!(revert.kind, RevertKind::Error);
+        assert_eq!(revert.reason, "not the owner");
+        assert_eq!(revert.panic_code, None);
+    }
+
+    #[tokio::test]
+    async fn decodes_panics() {
+        let data = Panic {
+            code: U256::from(0x11),
+        }
+        .abi_encode();
+
+        let revert = decode(&data, &CallTraceDecoderBuilder::new().build()).await;
+
+        assert_eq!(revert.kind, RevertKind::Panic);
+        assert_eq!(revert.reason, PanicKind::ArithmeticOverflow.as_str());
+        assert_eq!(revert.panic_code, Some(U256::from(0x11)));
+    }
+
+    #[tokio::test]
+    async fn decodes_custom_errors_known_to_the_decoder() {
+        let data = InsufficientBalance {
+            available: U256::from(1),
+            required: U256::from(2),
+        }
+        .abi_encode();
+        let mut decoder = CallTraceDecoderBuilder::new().build();
+        decoder.revert_decoder.push_error(
+            Error::parse("error InsufficientBalance(uint256 available, uint256 required)").unwrap(),
+        );
+
+        let revert = decode(&data, &decoder).await;
+
+        assert_eq!(revert.kind, RevertKind::Custom);
+        assert_eq!(
+            revert.error.as_deref(),
+            Some("InsufficientBalance(uint256,uint256)")
+        );
+        assert_eq!(revert.args, Some(vec![Value::from("1"), Value::from("2")]));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_reason_of_unknown_reverts() {
+        let decoder = CallTraceDecoderBuilder::new().build();
+
+        for data in [&[][..], &[0xde, 0xad, 0xbe, 0xef][..]] {
+            let revert = decode(data, &decoder).await;
+
+            assert_eq!(revert.kind, RevertKind::Unknown);
+            assert_eq!(revert.reason, "execution reverted");
+        }
+    }
+
+    #[test]
+    fn formats_values_as_json() {
+        let address = Address::repeat_byte(0xab);
+        let value = DynSolValue::Tuple(vec![
+            DynSolValue::Address(address),
+            DynSolValue::Uint(U256::MAX, 256),
+            DynSolValue::Bool(true),
+            DynSolValue::Bytes(vec![0x12, 0x34]),
+            DynSolValue::Array(vec![DynSolValue::String("a".to_string())]),
+        ]);
+
+        assert_eq!(
+            to_json(&value),
+            serde_json::json!([
+                address.to_checksum(None),
+                U256::MAX.to_string(),
+                true,
+                "0x1234",
+                ["a"],
+            ])
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use eyre::Report;
+use revm_primitives::InvalidTransaction;
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
 use std::{convert::Infallible, error::Error};
 
 use warp::{body::BodyDeserializeError, hyper::StatusCode, reject::Reject, Rejection, Reply};
@@ -45,11 +47,31 @@ pub struct NoBlockNumberError();
 
 impl Reject for NoBlockNumberError {}
 
+#[derive(Debug)]
+pub struct InsufficientFundsError();
+
+impl Reject for InsufficientFundsError {}
+
+#[derive(Debug)]
+pub struct NoGasLimitError();
+
+impl Reject for NoGasLimitError {}
+
 #[derive(Debug)]
 pub struct StateNotFound();
 
 impl Reject for StateNotFound {}
 
+#[derive(Debug)]
+pub struct TooManyStatefulSimulations();
+
+impl Reject for TooManyStatefulSimulations {}
+
+#[derive(Debug)]
+pub struct SnapshotNotFound();
+
+impl Reject for SnapshotNotFound {}
+
 #[derive(Debug)]
 pub struct OverrideError;
 
@@ -60,6 +82,18 @@ pub struct EvmError(pub Report);
 
 impl Reject for EvmError {}
 
+impl EvmError {
+    /// Whether the sender can't pay for the gas limit, blobs and value up front.
+    pub fn is_lack_of_funds(&self) -> bool {
+        self.0.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<InvalidTransaction>(),
+                Some(InvalidTransaction::LackOfFundForMaxFee { .. })
+            )
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct EvmCreateError(pub Report);
 
@@ -70,6 +104,45 @@ pub struct FailedSettingBlockTimestampError();
 
 impl Reject for FailedSettingBlockTimestampError {}
 
+#[derive(Debug)]
+pub struct InvalidRawTransactionError();
+
+impl Reject for InvalidRawTransactionError {}
+
+/// An authorization that can't be converted to the one revm takes, rejected instead of
+/// running the transaction without it.
+#[derive(Debug)]
+pub struct InvalidAuthorizationError();
+
+impl Display for InvalidAuthorizationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid authorization")
+    }
+}
+
+impl Error for InvalidAuthorizationError {}
+
+#[derive(Debug)]
+pub struct InvalidTracerError();
+
+impl Reject for InvalidTracerError {}
+
+/// `salt` deploys through the CREATE2 deployer, which a transaction to another contract can't.
+#[derive(Debug)]
+pub struct SaltWithToError();
+
+impl Reject for SaltWithToError {}
+
+#[derive(Debug)]
+pub struct TransactionNotFoundError();
+
+impl Reject for TransactionNotFoundError {}
+
+#[derive(Debug)]
+pub struct ProviderError(pub Report);
+
+impl Reject for ProviderError {}
+
 pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
     let code;
     let message: String;
@@ -80,6 +153,15 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     } else if let Some(_e) = err.find::<StateNotFound>() {
         code = StatusCode::NOT_FOUND;
         message = "STATE_NOT_FOUND".to_string();
+    } else if let Some(_e) = err.find::<TooManyStatefulSimulations>() {
+        code = StatusCode::TOO_MANY_REQUESTS;
+        message = "TOO_MANY_STATEFUL_SIMULATIONS".to_string();
+    } else if let Some(_e) = err.find::<SnapshotNotFound>() {
+        code = StatusCode::NOT_FOUND;
+        message = "SNAPSHOT_NOT_FOUND".to_string();
+    } else if let Some(_e) = err.find::<TransactionNotFoundError>() {
+        code = StatusCode::NOT_FOUND;
+        message = "TRANSACTION_NOT_FOUND".to_string();
     } else if let Some(NoURLForChainIdError) = err.find() {
         code = StatusCode::BAD_REQUEST;
         message = "CHAIN_ID_NOT_SUPPORTED".to_string();
@@ -98,6 +180,21 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     } else if let Some(_e) = err.find::<NoBlockNumberError>() {
         code = StatusCode::BAD_REQUEST;
         message = "NO_BLOCK_NUMBER".to_string();
+    } else if let Some(_e) = err.find::<InvalidRawTransactionError>() {
+        code = StatusCode::BAD_REQUEST;
+        message = "INVALID_RAW_TRANSACTION".to_string();
+    } else if let Some(_e) = err.find::<InvalidTracerError>() {
+        code = StatusCode::BAD_REQUEST;
+        message = "INVALID_TRACER".to_string();
+    } else if let Some(_e) = err.find::<SaltWithToError>() {
+        code = StatusCode::BAD_REQUEST;
+        message = "SALT_WITH_TO".to_string();
+    } else if let Some(_e) = err.find::<InsufficientFundsError>() {
+        code = StatusCode::BAD_REQUEST;
+        message = "INSUFFICIENT_FUNDS".to_string();
+    } else if let Some(_e) = err.find::<NoGasLimitError>() {
+        code = StatusCode::BAD_REQUEST;
+        message = "NO_GAS_LIMIT".to_string();
     } else if let Some(_e) = err.find::<OverrideError>() {
         code = StatusCode::INTERNAL_SERVER_ERROR;
         message = "OVERRIDE_ERROR".to_string();
@@ -105,6 +202,12 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
         if _e.0.to_string().contains("CallGasCostMoreThanGasLimit") {
             code = StatusCode::BAD_REQUEST;
             message = "OUT_OF_GAS".to_string();
+        } else if _e.0.downcast_ref::<InvalidAuthorizationError>().is_some() {
+            code = StatusCode::BAD_REQUEST;
+            message = "INVALID_AUTHORIZATION".to_string();
+        } else if _e.is_lack_of_funds() {
+            code = StatusCode::BAD_REQUEST;
+            message = "INSUFFICIENT_FUNDS".to_string();
         } else {
             code = StatusCode::INTERNAL_SERVER_ERROR;
             message = "EVM_ERROR".to_string();
@@ -121,6 +224,9 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     } else if let Some(_e) = err.find::<EvmCreateError>() {
         code = StatusCode::INTERNAL_SERVER_ERROR;
         message = "EVM_CREATE_ERROR".to_string();
+    } else if let Some(_e) = err.find::<ProviderError>() {
+        code = StatusCode::BAD_GATEWAY;
+        message = "PROVIDER_ERROR".to_string();
     } else if let Some(_e) = err.find::<FailedSettingBlockNumberError>() {
         code = StatusCode::INTERNAL_SERVER_ERROR;
         message = "FAILED_SETTING_BLOCK_NUMBER".to_string();
@@ -1,54 +1,149 @@
 use alloy::eips::eip2930::AccessList;
-use alloy::primitives::{Address, Bytes, Log, U256};
+use alloy::eips::eip4844::DATA_GAS_PER_BLOB;
+use alloy::eips::eip7702::SignedAuthorization;
+use alloy::json_abi::JsonAbi;
+use alloy::primitives::{Address, Bytes, Log, TxKind, B256, U256};
+use alloy::rlp::Decodable;
+use alloy_rpc_types_trace::geth::{
+    CallConfig, FourByteFrame, GethDefaultTracingConfig, GethTrace, NoopFrame, PreStateConfig,
+};
 use foundry_config::Chain;
-use foundry_evm::backend::Backend;
-use foundry_evm::executors::{Executor, ExecutorBuilder};
+use foundry_evm::backend::{Backend, CowBackend, DatabaseExt, RevertStateSnapshotAction};
+use foundry_evm::constants::DEFAULT_CREATE2_DEPLOYER;
+use foundry_evm::executors::{Executor, ExecutorBuilder, RawCallResult};
 use foundry_evm::fork::CreateFork;
 use foundry_evm::opts::EvmOpts;
-use foundry_evm::traces::identifier::{EtherscanIdentifier, SignaturesIdentifier};
+use foundry_evm::traces::identifier::{
+    EtherscanIdentifier, SignaturesIdentifier, SingleSignaturesIdentifier,
+};
 use foundry_evm::traces::{
-    CallTraceArena, CallTraceDecoder, CallTraceDecoderBuilder, CallTraceNode, TraceWriter,
+    decode_trace_arena, CallTraceArena, CallTraceDecoder, CallTraceDecoderBuilder, CallTraceNode,
+    TraceMode, TraceWriter,
+};
+use revm::inspectors::NoOpInspector;
+use revm::{
+    inspector_handle_register, interpreter::InstructionResult, DatabaseCommit, DatabaseRef,
+    Inspector, JournaledState,
+};
+use revm_inspectors::access_list::AccessListInspector;
+use revm_inspectors::tracing::{FourByteInspector, TracingInspector, TracingInspectorConfig};
+use revm_primitives::{
+    Account, AccountInfo, AuthorizationList, BlobExcessGasAndPrice, Bytecode, Env,
+    EnvWithHandlerCfg, EvmState, EvmStorageSlot, Output, ResultAndState, SpecId,
 };
-use revm::{interpreter::InstructionResult, DatabaseCommit, DatabaseRef};
-use revm_primitives::{Account, Bytecode, Env, EvmStorageSlot};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::errors::{EvmCreateError, EvmError, OverrideError};
-use crate::simulation::CallTrace;
+use crate::decode::{decode_logs, decode_revert, DecodedLog, RevertInfo};
+use crate::errors::{EvmCreateError, EvmError, InvalidAuthorizationError, OverrideError};
+use crate::simulation::{
+    AccountDiff, BlockOverrides, CallTrace, Delegation, Deployment, ValueChange,
+};
+use crate::step_trace::{StepTrace, StepTraceOptions, StepTracer};
 
 #[derive(Debug, Clone)]
 pub struct CallRawRequest {
     pub from: Address,
-    pub to: Address,
+    pub to: Option<Address>,
+    pub salt: Option<B256>,
     pub value: Option<U256>,
     pub data: Option<Bytes>,
+    pub gas_price: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub nonce: Option<u64>,
     pub access_list: Option<AccessList>,
+    pub authorization_list: Option<Vec<SignedAuthorization>>,
+    pub blob_versioned_hashes: Option<Vec<B256>>,
+    pub max_fee_per_blob_gas: Option<U256>,
     pub format_trace: bool,
+    /// Look up unknown contracts on Etherscan and unknown selectors in the signature database
+    /// when decoding. Implied by `format_trace`
+    pub decode_online: bool,
+    pub state_diff: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct CallRawResult {
     pub gas_used: u64,
+    pub effective_gas_price: U256,
+    pub total_fee: U256,
     pub block_number: u64,
     pub success: bool,
     pub trace: Option<CallTraceArena>,
     pub logs: Vec<Log>,
+    pub decoded_logs: Vec<DecodedLog>,
     pub exit_reason: InstructionResult,
     pub return_data: Bytes,
     pub formatted_trace: Option<String>,
+    pub revert: Option<RevertInfo>,
+    pub deployment: Option<Deployment>,
+    pub delegations: Vec<Delegation>,
+    pub state_diff: Option<HashMap<Address, AccountDiff>>,
 }
 
 impl From<CallTraceNode> for CallTrace {
     fn from(item: CallTraceNode) -> Self {
+        let (function, args) = match item.trace.decoded.call_data {
+            Some(call_data) => (Some(call_data.signature), Some(call_data.args)),
+            None => (None, None),
+        };
+
         CallTrace {
+            index: item.idx,
+            parent: item.parent,
+            children: item.children,
+            depth: item.trace.depth,
             call_type: item.trace.kind,
             from: item.trace.caller,
             to: item.trace.address,
             value: item.trace.value,
+            input: item.trace.data,
+            output: item.trace.output,
+            gas_used: item.trace.gas_used,
+            gas_limit: item.trace.gas_limit,
+            success: item.trace.success,
+            status: item.trace.status,
+            label: item.trace.decoded.label,
+            function,
+            args,
+            decoded_output: item.trace.decoded.return_data,
         }
     }
 }
 
+/// Upper bound for gas estimation when the request has no gas limit, the same as geth's default
+/// RPC gas cap.
+pub const DEFAULT_GAS_CAP: u64 = 50_000_000;
+
+/// Milliseconds between blocks when advancing the block number, Ethereum mainnet's slot time.
+pub const DEFAULT_BLOCK_TIME_MS: u64 = 12_000;
+
+#[derive(Debug, Clone)]
+pub struct GasEstimate {
+    pub gas_limit: Option<u64>,
+    pub result: CallRawResult,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessListResult {
+    pub access_list: AccessList,
+    pub gas_used: u64,
+    pub gas_used_without_access_list: u64,
+    pub success: bool,
+}
+
+/// The geth tracers [`Evm::geth_trace`] can run, `StructLogger` being geth's default.
+#[derive(Debug, Clone)]
+pub enum GethTracer {
+    StructLogger(GethDefaultTracingConfig),
+    Call(CallConfig),
+    PreState(PreStateConfig),
+    FourByte,
+    Noop,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct StorageOverride {
     pub slots: HashMap<U256, U256>,
@@ -59,6 +154,58 @@ pub struct Evm {
     executor: Executor,
     decoder: CallTraceDecoder,
     etherscan_identifier: Option<EtherscanIdentifier>,
+    decoding: Arc<DecoderConfig>,
+    /// Shared with the decoder, kept to look up custom errors it doesn't know
+    signatures_identifier: Option<SingleSignaturesIdentifier>,
+    gas_limit: u64,
+    block_gas_limit: Option<U256>,
+    block_time_ms: u64,
+    /// Time advanced past the current timestamp that doesn't add up to a full second yet
+    timestamp_remainder_ms: u64,
+    snapshots: HashMap<U256, Snapshot>,
+}
+
+/// What traces, logs and reverts are decoded with, shared by every [`Evm`].
+#[derive(Debug, Clone)]
+pub struct DecoderConfig {
+    /// The decoder every EVM starts from a copy of, knowing the local ABIs
+    pub decoder: CallTraceDecoder,
+    pub etherscan_key: Option<String>,
+    /// Where looked up signatures are cached, defaults to foundry's cache directory
+    pub signatures_cache_dir: Option<PathBuf>,
+    /// Never look up contracts on Etherscan or signatures online, only decode with known ABIs
+    /// and cached signatures
+    pub offline: bool,
+}
+
+impl DecoderConfig {
+    /// Builds the base decoder once, so the local ABIs aren't added again for every EVM.
+    pub fn new(
+        local_abis: &[JsonAbi],
+        etherscan_key: Option<String>,
+        signatures_cache_dir: Option<PathBuf>,
+        offline: bool,
+    ) -> Self {
+        let mut decoder = CallTraceDecoderBuilder::new().with_verbosity(5).build();
+        for abi in local_abis {
+            add_abi(&mut decoder, abi);
+        }
+
+        DecoderConfig {
+            decoder,
+            etherscan_key,
+            signatures_cache_dir,
+            offline,
+        }
+    }
+}
+
+/// The state of an [`Evm`] a snapshot restores besides that of its backend and environment.
+#[derive(Debug, Clone, Copy)]
+struct Snapshot {
+    gas_limit: u64,
+    block_gas_limit: Option<U256>,
+    timestamp_remainder_ms: u64,
 }
 
 impl Evm {
@@ -67,7 +214,7 @@ impl Evm {
         fork_url: String,
         fork_block_number: Option<u64>,
         gas_limit: u64,
-        etherscan_key: Option<String>,
+        decoding: Arc<DecoderConfig>,
     ) -> Result<Self, EvmCreateError> {
         let evm_opts = EvmOpts {
             fork_url: Some(fork_url.clone()),
@@ -95,84 +242,252 @@ impl Evm {
 
         let db = Backend::spawn(Some(fork_opts.clone()));
 
-        let builder = ExecutorBuilder::default().gas_limit(gas_limit.into());
+        let builder = ExecutorBuilder::default()
+            .gas_limit(gas_limit.into())
+            .inspectors(|stack| stack.trace_mode(TraceMode::Call));
 
         let executor = builder.build(env.unwrap_or(fork_opts.env.clone()), db);
 
-        let foundry_config = foundry_config::Config {
-            etherscan_api_key: etherscan_key,
-            ..Default::default()
-        };
+        let etherscan_identifier = etherscan_identifier(
+            decoding.etherscan_key.clone(),
+            fork_opts.env.cfg.chain_id,
+            decoding.offline,
+        );
 
-        let chain: Chain = fork_opts.env.cfg.chain_id.into();
-        let etherscan_identifier =
-            EtherscanIdentifier::new(&foundry_config, Some(chain)).unwrap_or_default();
-
-        let decoder = CallTraceDecoderBuilder::new().with_verbosity(5);
-
-        let decoder = if let Ok(identifier) =
-            SignaturesIdentifier::new(foundry_config::Config::foundry_cache_dir(), false)
-        {
-            decoder.with_signature_identifier(identifier)
-        } else {
-            decoder
-        };
+        // Offline, signatures are only looked up in the cache
+        let signatures_identifier = SignaturesIdentifier::new(
+            decoding
+                .signatures_cache_dir
+                .clone()
+                .or_else(foundry_config::Config::foundry_cache_dir),
+            decoding.offline,
+        )
+        .ok();
+        let mut decoder = decoding.decoder.clone();
+        decoder.signature_identifier = signatures_identifier.clone();
 
         Ok(Evm {
             executor,
-            decoder: decoder.build(),
+            decoder,
             etherscan_identifier,
+            decoding,
+            signatures_identifier,
+            gas_limit,
+            block_gas_limit: None,
+            block_time_ms: DEFAULT_BLOCK_TIME_MS,
+            timestamp_remainder_ms: 0,
+            snapshots: HashMap::new(),
         })
     }
 
+    /// Creates an independent copy of this EVM, including its forked state and block
+    /// environment. Transactions committed to either copy are not visible to the other.
+    pub fn fork(&self) -> Self {
+        Evm {
+            executor: self.executor.clone(),
+            decoder: self.decoder.clone(),
+            etherscan_identifier: etherscan_identifier(
+                self.decoding.etherscan_key.clone(),
+                self.get_chain_id(),
+                self.decoding.offline,
+            ),
+            decoding: self.decoding.clone(),
+            signatures_identifier: self.signatures_identifier.clone(),
+            gas_limit: self.gas_limit,
+            block_gas_limit: self.block_gas_limit,
+            block_time_ms: self.block_time_ms,
+            timestamp_remainder_ms: self.timestamp_remainder_ms,
+            snapshots: self.snapshots.clone(),
+        }
+    }
+
     pub async fn call_raw(&mut self, call: CallRawRequest) -> Result<CallRawResult, EvmError> {
-        self.set_access_list(call.access_list)?;
-        let mut res = self
-            .executor
-            .call_raw(
-                call.from,
-                call.to,
-                call.data.unwrap_or_default(),
-                call.value.unwrap_or_default(),
-            )
-            .map_err(|err| {
-                log::error!("Error calling raw: {:?}", err);
-                EvmError(err)
-            })?;
+        let (env, create2_address) = self.build_env(&call)?;
+        let res = self.executor.call_with_env(env).map_err(|err| {
+            log::error!("Error calling raw: {:?}", err);
+            EvmError(err)
+        })?;
 
-        let formatted_trace = if call.format_trace {
-            let mut trace_writer = TraceWriter::new(Vec::<u8>::new());
-            if let Some(trace) = &mut res.traces {
-                if let Some(identifier) = &mut self.etherscan_identifier {
-                    self.decoder.identify(trace, identifier);
-                }
-                trace_writer.write_arena(trace).map_err(|err| {
-                    log::error!("Error writing trace: {:?}", err);
-                    EvmError(err.into())
-                })?;
-            }
-            Some(
-                String::from_utf8(trace_writer.into_writer()).map_err(|err| {
-                    log::error!("Error converting trace to string: {:?}", err);
-                    EvmError(err.into())
-                })?,
-            )
-        } else {
-            None
+        self.call_raw_result(res, &call, create2_address).await
+    }
+
+    /// Binary searches the lowest gas limit, up to `gas_cap`, that `call` succeeds with. The
+    /// result is that of the call at the estimated limit, or at `gas_cap` if it always fails.
+    pub async fn estimate_gas(
+        &mut self,
+        call: CallRawRequest,
+        gas_cap: u64,
+    ) -> Result<GasEstimate, EvmError> {
+        let estimate = self.search_gas_limit(call, gas_cap).await;
+        self.executor.set_gas_limit(self.gas_limit);
+        estimate
+    }
+
+    async fn search_gas_limit(
+        &mut self,
+        call: CallRawRequest,
+        gas_cap: u64,
+    ) -> Result<GasEstimate, EvmError> {
+        // Like geth, only search the limits the sender can pay for at its max fee
+        let gas_cap = self.affordable_gas_limit(&call, gas_cap)?;
+        let Some(gas_used) = self.try_gas_limit(&call, gas_cap)? else {
+            self.executor.set_gas_limit(gas_cap);
+            return Ok(GasEstimate {
+                gas_limit: None,
+                result: self.call_raw(call).await?,
+            });
         };
 
-        Ok(CallRawResult {
-            gas_used: res.gas_used,
-            block_number: res.env.block.number.to(),
-            success: !res.reverted,
-            trace: None,
-            logs: res.logs,
-            exit_reason: res.exit_reason,
-            return_data: res.result,
-            formatted_trace,
+        // The gas used is only a lower bound: refunds and the 63/64 rule for subcalls can
+        // make a call need a higher limit than what it ends up using.
+        let mut lo = gas_used.saturating_sub(1);
+        let mut hi = gas_cap;
+        while lo + 1 < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.try_gas_limit(&call, mid) {
+                Ok(Some(_)) => hi = mid,
+                // The sender can't pay for this limit, whatever the call needs
+                Err(err) if err.is_lack_of_funds() => hi = mid,
+                // Limits below the intrinsic gas are rejected before execution
+                _ => lo = mid,
+            }
+        }
+
+        // Only the call at the estimated limit is decoded
+        self.executor.set_gas_limit(hi);
+        Ok(GasEstimate {
+            gas_limit: Some(hi),
+            result: self.call_raw(call).await?,
         })
     }
 
+    /// Lowers `gas_cap` to the most gas the sender of `call` can pay for at its max fee per gas,
+    /// after its value and blobs. Calls that don't pay for gas are only capped by `gas_cap`.
+    fn affordable_gas_limit(&self, call: &CallRawRequest, gas_cap: u64) -> Result<u64, EvmError> {
+        let max_fee_per_gas = self.max_fee_per_gas(call, None);
+        if max_fee_per_gas.is_zero() {
+            return Ok(gas_cap);
+        }
+
+        let available = self
+            .get_balance(call.from)?
+            .saturating_sub(self.max_cost(call, 0, None));
+        Ok(gas_cap.min((available / max_fee_per_gas).saturating_to()))
+    }
+
+    /// Runs `call` with `gas_limit` without decoding it, returning the gas it used if it
+    /// succeeded.
+    fn try_gas_limit(
+        &mut self,
+        call: &CallRawRequest,
+        gas_limit: u64,
+    ) -> Result<Option<u64>, EvmError> {
+        self.executor.set_gas_limit(gas_limit);
+        let (env, _) = self.build_env(call)?;
+        let res = self.executor.call_with_env(env).map_err(|err| {
+            log::error!("Error calling raw: {:?}", err);
+            EvmError(err)
+        })?;
+
+        Ok((!res.reverted).then_some(res.gas_used))
+    }
+
+    /// Generates the access list of `call` the way geth's `eth_createAccessList` does: it is
+    /// traced with the accounts and slots it touched until the list no longer changes, since
+    /// the list itself can change the gas left and with it the execution path.
+    pub fn create_access_list(&self, call: &CallRawRequest) -> Result<AccessListResult, EvmError> {
+        let (mut env, _) = self.build_env(call)?;
+
+        env.tx.access_list = Default::default();
+        let without_access_list = self.inspect(env.clone(), &mut NoOpInspector)?;
+
+        let mut access_list = call.access_list.clone().unwrap_or_default();
+        loop {
+            env.tx.access_list = access_list.clone().into();
+            let mut inspector = AccessListInspector::new(access_list.clone());
+            let res = self.inspect(env.clone(), &mut inspector)?;
+
+            let traced_access_list = inspector.into_access_list();
+            if traced_access_list == access_list {
+                return Ok(AccessListResult {
+                    access_list,
+                    gas_used: res.result.gas_used(),
+                    gas_used_without_access_list: without_access_list.result.gas_used(),
+                    success: res.result.is_success(),
+                });
+            }
+            access_list = traced_access_list;
+        }
+    }
+
+    /// Traces `call` with one of geth's built-in tracers, in the format of geth's
+    /// `debug_traceCall`. Nothing is committed.
+    pub fn geth_trace(
+        &mut self,
+        call: &CallRawRequest,
+        gas_limit: u64,
+        tracer: GethTracer,
+    ) -> Result<GethTrace, EvmError> {
+        let env = self.build_env_with_gas_limit(call, gas_limit)?;
+
+        match tracer {
+            GethTracer::StructLogger(config) => {
+                let mut inspector =
+                    TracingInspector::new(TracingInspectorConfig::from_geth_config(&config));
+                let res = self.inspect(env, &mut inspector)?;
+                let gas_used = res.result.gas_used();
+                let return_value = res.result.into_output().unwrap_or_default();
+                Ok(inspector
+                    .into_geth_builder()
+                    .geth_traces(gas_used, return_value, config)
+                    .into())
+            }
+            GethTracer::Call(config) => {
+                let mut inspector =
+                    TracingInspector::new(TracingInspectorConfig::from_geth_call_config(&config));
+                let res = self.inspect(env, &mut inspector)?;
+                Ok(inspector
+                    .into_geth_builder()
+                    .geth_call_traces(config, res.result.gas_used())
+                    .into())
+            }
+            GethTracer::PreState(config) => {
+                let mut inspector = TracingInspector::new(
+                    TracingInspectorConfig::from_geth_prestate_config(&config),
+                );
+                let res = self.inspect(env, &mut inspector)?;
+                let frame = inspector
+                    .into_geth_builder()
+                    .geth_prestate_traces(&res, &config, self.executor.backend())
+                    .map_err(|err| {
+                        log::error!("Error building prestate trace: {:?}", err);
+                        EvmError(err.into())
+                    })?;
+                Ok(frame.into())
+            }
+            GethTracer::FourByte => {
+                let mut inspector = FourByteInspector::default();
+                self.inspect(env, &mut inspector)?;
+                Ok(FourByteFrame::from(&inspector).into())
+            }
+            GethTracer::Noop => Ok(NoopFrame::default().into()),
+        }
+    }
+
+    /// Records every opcode `call` executes. Nothing is committed.
+    pub fn step_trace(
+        &mut self,
+        call: &CallRawRequest,
+        gas_limit: u64,
+        options: &StepTraceOptions,
+    ) -> Result<StepTrace, EvmError> {
+        let env = self.build_env_with_gas_limit(call, gas_limit)?;
+        let mut inspector = StepTracer::new(options);
+        self.inspect(env, &mut inspector)?;
+
+        Ok(inspector.into_trace())
+    }
+
     pub fn override_account(
         &mut self,
         address: Address,
@@ -224,86 +539,691 @@ impl Evm {
         Ok(())
     }
 
+    /// Overrides the block environment that following calls run in. Block hashes are written
+    /// to the fork's cache, where `BLOCKHASH` looks them up before asking the RPC.
+    pub fn override_block(&mut self, overrides: &BlockOverrides) -> Result<(), OverrideError> {
+        let block = &mut self.executor.env_mut().block;
+        if let Some(coinbase) = overrides.coinbase {
+            block.coinbase = coinbase;
+        }
+        if let Some(base_fee) = overrides.base_fee {
+            block.basefee = base_fee;
+        }
+        if let Some(prevrandao) = overrides.prevrandao {
+            block.prevrandao = Some(prevrandao);
+        }
+        if let Some(difficulty) = overrides.difficulty {
+            block.difficulty = difficulty;
+        }
+        if let Some(blob_base_fee) = overrides.blob_base_fee {
+            block.blob_excess_gas_and_price = Some(BlobExcessGasAndPrice {
+                excess_blob_gas: block
+                    .blob_excess_gas_and_price
+                    .as_ref()
+                    .map(|blob| blob.excess_blob_gas)
+                    .unwrap_or_default(),
+                blob_gasprice: blob_base_fee.saturating_to(),
+            });
+        }
+        if let Some(gas_limit) = overrides.gas_limit {
+            block.gas_limit = U256::from(gas_limit);
+            self.block_gas_limit = Some(U256::from(gas_limit));
+        }
+
+        if let Some(block_hashes) = &overrides.block_hash {
+            let db = self
+                .executor
+                .backend_mut()
+                .active_fork_db_mut()
+                .ok_or(OverrideError)?;
+            db.block_hashes.extend(
+                block_hashes
+                    .iter()
+                    .map(|(number, hash)| (U256::from(*number), *hash)),
+            );
+        }
+
+        Ok(())
+    }
+
     pub async fn transact_raw(
         &mut self,
         call: CallRawRequest,
         gas_limit: u64,
     ) -> Result<CallRawResult, EvmError> {
         self.executor.set_gas_limit(gas_limit);
-        self.set_access_list(call.access_list)?;
-        let mut res = self
+        self.gas_limit = gas_limit;
+        let (env, create2_address) = self.build_env(&call)?;
+        let res = self.executor.call_with_env(env).map_err(|err| {
+            log::error!("Error transacting raw: {:?}", err);
+            EvmError(err)
+        })?;
+
+        // Commit only once the result has been processed, so the state diff can still read
+        // the pre-transaction state from the backend.
+        let state_changeset = res.state_changeset.clone();
+        let result = self.call_raw_result(res, &call, create2_address).await?;
+        self.executor.backend_mut().commit(state_changeset);
+
+        Ok(result)
+    }
+
+    /// Executes `call` without decoding it, for callers that only need its outcome. Nothing is
+    /// committed, see [`Evm::commit`].
+    pub fn execute_raw(
+        &mut self,
+        call: &CallRawRequest,
+        gas_limit: u64,
+    ) -> Result<RawCallResult, EvmError> {
+        let env = self.build_env_with_gas_limit(call, gas_limit)?;
+        self.executor.call_with_env(env).map_err(|err| {
+            log::error!("Error executing raw: {:?}", err);
+            EvmError(err)
+        })
+    }
+
+    /// Executes and commits `call` without decoding it, for transactions only the state they
+    /// leave behind is needed of. Returns whether it succeeded.
+    pub fn commit_raw(&mut self, call: &CallRawRequest, gas_limit: u64) -> Result<bool, EvmError> {
+        let res = self.execute_raw(call, gas_limit)?;
+        self.commit(res.state_changeset);
+
+        Ok(!res.reverted)
+    }
+
+    /// Commits the state changes of an execution, e.g. of [`Evm::execute_raw`].
+    pub fn commit(&mut self, state_changeset: EvmState) {
+        self.executor.backend_mut().commit(state_changeset);
+    }
+
+    pub async fn set_block(&mut self, number: U256) -> Result<(), EvmError> {
+        self.executor.env_mut().block.number = number;
+        Ok(())
+    }
+
+    pub fn get_block(&self) -> U256 {
+        self.executor.env().block.number
+    }
+
+    pub async fn set_block_timestamp(&mut self, timestamp: U256) -> Result<(), EvmError> {
+        self.executor.env_mut().block.timestamp = timestamp;
+        self.timestamp_remainder_ms = 0;
+        Ok(())
+    }
+
+    /// Moves to block `number`, advancing the timestamp by the time the skipped blocks take at
+    /// `block_time_ms`, or at the block time of this EVM if `None`. Sub-second block times
+    /// carry over, so four 250ms blocks advance the timestamp by one second.
+    pub async fn advance_block(
+        &mut self,
+        number: U256,
+        block_time_ms: Option<u64>,
+    ) -> Result<(), EvmError> {
+        let blocks: u64 = number.saturating_sub(self.get_block()).saturating_to();
+        let elapsed_ms = blocks
+            .saturating_mul(block_time_ms.unwrap_or(self.block_time_ms))
+            .saturating_add(self.timestamp_remainder_ms);
+
+        let block = &mut self.executor.env_mut().block;
+        block.number = number;
+        block.timestamp = block
+            .timestamp
+            .saturating_add(U256::from(elapsed_ms / 1000));
+        self.timestamp_remainder_ms = elapsed_ms % 1000;
+
+        Ok(())
+    }
+
+    pub fn set_block_time_ms(&mut self, block_time_ms: u64) {
+        self.block_time_ms = block_time_ms;
+    }
+
+    /// Decodes the functions, events and errors of `abi` wherever they appear, including in
+    /// contracts that aren't verified on Etherscan.
+    pub fn add_abi(&mut self, abi: &JsonAbi) {
+        add_abi(&mut self.decoder, abi);
+    }
+
+    /// Names `address` in traces and decoded logs. Takes precedence over Etherscan's name.
+    pub fn add_label(&mut self, address: Address, label: String) {
+        self.decoder.labels.insert(address, label);
+    }
+
+    pub fn get_block_timestamp(&self) -> U256 {
+        self.executor.env().block.timestamp
+    }
+
+    pub fn get_chain_id(&self) -> u64 {
+        self.executor.env().cfg.chain_id
+    }
+
+    pub fn snapshot(&mut self) -> U256 {
+        let journaled_state = JournaledState::new(self.executor.spec_id(), Default::default());
+        let env = self.executor.env().env.as_ref().clone();
+        let id = self
             .executor
-            .transact_raw(
-                call.from,
-                call.to,
-                call.data.unwrap_or_default(),
-                call.value.unwrap_or_default(),
+            .backend_mut()
+            .snapshot_state(&journaled_state, &env);
+        self.snapshots.insert(
+            id,
+            Snapshot {
+                gas_limit: self.gas_limit,
+                block_gas_limit: self.block_gas_limit,
+                timestamp_remainder_ms: self.timestamp_remainder_ms,
+            },
+        );
+
+        id
+    }
+
+    /// Reverts the state, block environment and gas limits to what they were when snapshot
+    /// `id` was taken. The snapshot is kept so the same point can be reverted to again.
+    /// Returns `false` if there is no snapshot with that id.
+    pub fn revert_to_snapshot(&mut self, id: U256) -> bool {
+        let journaled_state = JournaledState::new(self.executor.spec_id(), Default::default());
+        let mut env = self.executor.env().env.as_ref().clone();
+        let reverted = self
+            .executor
+            .backend_mut()
+            .revert_state(
+                id,
+                &journaled_state,
+                &mut env,
+                RevertStateSnapshotAction::RevertKeep,
             )
+            .is_some();
+
+        if reverted {
+            self.executor.env_mut().env = Box::new(env);
+            if let Some(snapshot) = self.snapshots.get(&id).copied() {
+                self.gas_limit = snapshot.gas_limit;
+                self.executor.set_gas_limit(snapshot.gas_limit);
+                self.block_gas_limit = snapshot.block_gas_limit;
+                self.timestamp_remainder_ms = snapshot.timestamp_remainder_ms;
+            }
+        }
+
+        reverted
+    }
+
+    pub fn get_gas_limit(&self) -> u64 {
+        self.gas_limit
+    }
+
+    pub fn get_balance(&self, address: Address) -> Result<U256, EvmError> {
+        Ok(self.account_info(address)?.balance)
+    }
+
+    pub fn get_nonce(&self, address: Address) -> Result<u64, EvmError> {
+        Ok(self.account_info(address)?.nonce)
+    }
+
+    pub fn get_code(&self, address: Address) -> Result<Bytes, EvmError> {
+        let info = self.account_info(address)?;
+        let code = match info.code {
+            Some(code) => code,
+            None => self
+                .executor
+                .backend()
+                .code_by_hash_ref(info.code_hash)
+                .map_err(|err| {
+                    log::error!("Error reading account code: {:?}", err);
+                    EvmError(err.into())
+                })?,
+        };
+
+        Ok(code.original_bytes())
+    }
+
+    pub fn get_storage_at(&self, address: Address, slot: U256) -> Result<U256, EvmError> {
+        self.executor
+            .backend()
+            .storage_ref(address, slot)
             .map_err(|err| {
-                log::error!("Error transacting raw: {:?}", err);
-                EvmError(err)
-            })?;
+                log::error!("Error reading account storage: {:?}", err);
+                EvmError(err.into())
+            })
+    }
+
+    fn account_info(&self, address: Address) -> Result<AccountInfo, EvmError> {
+        let info = self.executor.backend().basic_ref(address).map_err(|err| {
+            log::error!("Error reading account: {:?}", err);
+            EvmError(err.into())
+        })?;
+
+        Ok(info.unwrap_or_default())
+    }
+
+    /// Builds the transaction environment for `call`. Without a `to` address the call is a
+    /// deployment: a plain CREATE, or a CREATE2 through the deterministic deployer if a salt
+    /// is given, in which case the address it will deploy to is returned as well.
+    fn build_env(
+        &self,
+        call: &CallRawRequest,
+    ) -> Result<(EnvWithHandlerCfg, Option<Address>), EvmError> {
+        let data = call.data.clone().unwrap_or_default();
+        let value = call.value.unwrap_or_default();
 
-        let formatted_trace = if call.format_trace {
-            let mut trace_writer = TraceWriter::new(Vec::<u8>::new());
-            if let Some(trace) = &mut res.traces {
-                if let Some(identifier) = &mut self.etherscan_identifier {
-                    self.decoder.identify(trace, identifier);
+        // A `salt` with `to` is rejected when the request is converted
+        let (transact_to, data, create2_address) = match (call.to, call.salt) {
+            (Some(to), _) => (TxKind::Call(to), data, None),
+            (None, None) => (TxKind::Create, data, None),
+            (None, Some(salt)) => {
+                let address = DEFAULT_CREATE2_DEPLOYER.create2_from_code(salt, &data);
+                let data = [salt.as_slice(), &data].concat().into();
+                (TxKind::Call(DEFAULT_CREATE2_DEPLOYER), data, Some(address))
+            }
+        };
+
+        let mut env = self
+            .executor
+            .build_test_env(call.from, transact_to, data, value);
+
+        // The test environment zeroes the base fee and overwrites the block gas limit, restore
+        // both so contracts see the real block. The base fee is only enforced if the call asks
+        // to pay for gas.
+        let basefee = self.executor.env().block.basefee;
+        env.block.basefee = basefee;
+        if let Some(block_gas_limit) = self.block_gas_limit {
+            env.block.gas_limit = block_gas_limit;
+        }
+
+        if call.gas_price.is_none()
+            && call.max_fee_per_gas.is_none()
+            && call.max_priority_fee_per_gas.is_none()
+        {
+            env.cfg.disable_base_fee = true;
+        } else {
+            match call.gas_price {
+                Some(gas_price) => env.tx.gas_price = gas_price,
+                None => {
+                    let priority_fee = call.max_priority_fee_per_gas.unwrap_or_default();
+                    env.tx.gas_price = call.max_fee_per_gas.unwrap_or(basefee + priority_fee);
+                    env.tx.gas_priority_fee = Some(priority_fee);
                 }
-                trace_writer.write_arena(trace).map_err(|err| {
-                    log::error!("Error writing trace: {:?}", err);
-                    EvmError(err.into())
-                })?;
             }
+        }
+        env.tx.nonce = call.nonce;
+        env.tx.access_list = call.access_list.clone().unwrap_or_default();
+        env.tx.authorization_list = call
+            .authorization_list
+            .as_ref()
+            .map(|authorizations| {
+                authorizations
+                    .iter()
+                    .map(revm_authorization)
+                    .collect::<Result<_, _>>()
+                    .map(AuthorizationList::Signed)
+            })
+            .transpose()
+            .map_err(|err| EvmError(err.into()))?;
+        // revm only accepts authorizations from Prague on, while forks default to Cancun
+        if env.tx.authorization_list.is_some()
+            && !env.handler_cfg.spec_id.is_enabled_in(SpecId::PRAGUE)
+        {
+            env.handler_cfg.spec_id = SpecId::PRAGUE;
+        }
+        // Without blobs the transaction isn't a blob transaction, which revm rejects if it has a
+        // max fee per blob gas
+        if let Some(blob_hashes) = call
+            .blob_versioned_hashes
+            .as_ref()
+            .filter(|blob_hashes| !blob_hashes.is_empty())
+        {
+            env.tx.blob_hashes = blob_hashes.clone();
+            env.tx.max_fee_per_blob_gas = Some(self.max_fee_per_blob_gas(call));
+        }
+
+        Ok((env, create2_address))
+    }
+
+    /// Runs `env` with `inspector` on top of the current state without committing it, for
+    /// inspectors the executor doesn't come with.
+    fn inspect<I>(
+        &self,
+        env: EnvWithHandlerCfg,
+        inspector: &mut I,
+    ) -> Result<ResultAndState, EvmError>
+    where
+        I: for<'db> Inspector<CowBackend<'db>>,
+    {
+        let mut evm = revm::Evm::builder()
+            .with_db(CowBackend::new_borrowed(self.executor.backend()))
+            .with_external_context(inspector)
+            .with_env_with_handler_cfg(env)
+            .append_handler_register(inspector_handle_register)
+            .build();
+
+        evm.transact().map_err(|err| {
+            log::error!("Error inspecting call: {:?}", err);
+            EvmError(err.into())
+        })
+    }
+
+    /// Builds the environment for `call` as if the executor's gas limit was `gas_limit`.
+    fn build_env_with_gas_limit(
+        &mut self,
+        call: &CallRawRequest,
+        gas_limit: u64,
+    ) -> Result<EnvWithHandlerCfg, EvmError> {
+        self.executor.set_gas_limit(gas_limit);
+        let env = self.build_env(call);
+        self.executor.set_gas_limit(self.gas_limit);
+        Ok(env?.0)
+    }
+
+    /// The blob gas price `call` is willing to pay, by default the current blob base fee.
+    pub fn max_fee_per_blob_gas(&self, call: &CallRawRequest) -> U256 {
+        call.max_fee_per_blob_gas.unwrap_or_else(|| {
+            U256::from(
+                self.executor
+                    .env()
+                    .block
+                    .get_blob_gasprice()
+                    .unwrap_or_default(),
+            )
+        })
+    }
+
+    /// The most `call` can be charged with `gas_limit`, which its sender's balance must cover
+    /// up front: the whole gas limit at its max fee per gas, its blobs at their max fee and
+    /// its value. `overrides` are taken into account as if they were applied.
+    pub fn max_cost(
+        &self,
+        call: &CallRawRequest,
+        gas_limit: u64,
+        overrides: Option<&BlockOverrides>,
+    ) -> U256 {
+        let block = &self.executor.env().block;
+        let max_fee_per_gas = self.max_fee_per_gas(call, overrides);
+
+        let blobs = call.blob_versioned_hashes.as_ref().map_or(0, Vec::len);
+        let max_fee_per_blob_gas = call
+            .max_fee_per_blob_gas
+            .or_else(|| overrides.and_then(|overrides| overrides.blob_base_fee))
+            .unwrap_or_else(|| U256::from(block.get_blob_gasprice().unwrap_or_default()));
+
+        U256::from(gas_limit)
+            .saturating_mul(max_fee_per_gas)
+            .saturating_add(
+                U256::from(blobs as u64 * DATA_GAS_PER_BLOB).saturating_mul(max_fee_per_blob_gas),
+            )
+            .saturating_add(call.value.unwrap_or_default())
+    }
+
+    /// The most `call` pays per gas, zero if it doesn't pay for gas.
+    fn max_fee_per_gas(&self, call: &CallRawRequest, overrides: Option<&BlockOverrides>) -> U256 {
+        match (
+            call.gas_price,
+            call.max_fee_per_gas,
+            call.max_priority_fee_per_gas,
+        ) {
+            (Some(gas_price), _, _) => gas_price,
+            (None, Some(max_fee), _) => max_fee,
+            (None, None, Some(priority_fee)) => {
+                let basefee = overrides
+                    .and_then(|overrides| overrides.base_fee)
+                    .unwrap_or(self.executor.env().block.basefee);
+                basefee + priority_fee
+            }
+            (None, None, None) => U256::ZERO,
+        }
+    }
+
+    async fn call_raw_result(
+        &mut self,
+        res: RawCallResult,
+        call: &CallRawRequest,
+        create2_address: Option<Address>,
+    ) -> Result<CallRawResult, EvmError> {
+        let deployed_address = match &res.out {
+            Some(Output::Create(_, address)) => *address,
+            // The call to the deployer also succeeds when nothing was deployed, e.g. on chains
+            // it doesn't exist on
+            _ => create2_address.filter(|address| {
+                res.state_changeset
+                    .get(address)
+                    .and_then(|account| account.info.code.as_ref())
+                    .is_some_and(|code| !code.is_empty())
+            }),
+        };
+        let deployment = deployed_address
+            .filter(|_| !res.reverted)
+            .map(|address| Deployment {
+                address,
+                code_size: res
+                    .state_changeset
+                    .get(&address)
+                    .and_then(|account| account.info.code.as_ref())
+                    .map(|code| code.original_bytes().len())
+                    .unwrap_or_default(),
+                gas_used: res.gas_used,
+            });
+
+        let delegations = delegations(call, &res.state_changeset);
+
+        let state_diff = if call.state_diff {
+            Some(self.state_diff(&res.state_changeset)?)
+        } else {
+            None
+        };
+
+        // Without decoding asked for, only what the decoder already knows is used, so calls
+        // don't wait on Etherscan and the signature database
+        let online = call.format_trace || call.decode_online;
+        let mut trace = res.traces.map(|traces| traces.arena);
+        let formatted_trace = self
+            .decode_trace(trace.as_mut(), call.format_trace, online)
+            .await?;
+
+        let signatures = self.signatures_identifier.as_ref().filter(|_| online);
+        let decoded_logs = decode_logs(&res.logs, &self.decoder, signatures).await;
+
+        let revert = if res.reverted {
+            let reason = self
+                .decoder
+                .revert_decoder
+                .decode(&res.result, Some(res.exit_reason));
             Some(
-                String::from_utf8(trace_writer.into_writer()).map_err(|err| {
-                    log::error!("Error converting trace to string: {:?}", err);
-                    EvmError(err.into())
-                })?,
+                decode_revert(
+                    &res.result,
+                    reason,
+                    &self.decoder,
+                    signatures,
+                    trace.as_ref(),
+                )
+                .await,
             )
         } else {
             None
         };
 
+        let effective_gas_price = res.env.effective_gas_price();
+
         Ok(CallRawResult {
             gas_used: res.gas_used,
+            effective_gas_price,
+            total_fee: effective_gas_price * U256::from(res.gas_used),
             block_number: res.env.block.number.to(),
             success: !res.reverted,
-            trace: None,
+            trace,
             logs: res.logs,
+            decoded_logs,
             exit_reason: res.exit_reason,
             return_data: res.result,
             formatted_trace,
+            revert,
+            deployment,
+            delegations,
+            state_diff,
         })
     }
 
-    pub async fn set_block(&mut self, number: U256) -> Result<(), EvmError> {
-        self.executor.env_mut().block.number = number;
-        Ok(())
-    }
+    fn state_diff(&self, changeset: &EvmState) -> Result<HashMap<Address, AccountDiff>, EvmError> {
+        let mut state_diff = HashMap::new();
+        for (address, account) in changeset {
+            if !account.is_touched() {
+                continue;
+            }
 
-    pub fn get_block(&self) -> U256 {
-        self.executor.env().block.number
-    }
+            let before = self.account_info(*address)?;
+            let storage: HashMap<U256, ValueChange<U256>> = account
+                .storage
+                .iter()
+                .filter(|(_, slot)| slot.is_changed())
+                .map(|(key, slot)| {
+                    (
+                        *key,
+                        ValueChange {
+                            before: slot.original_value(),
+                            after: slot.present_value(),
+                        },
+                    )
+                })
+                .collect();
 
-    pub async fn set_block_timestamp(&mut self, timestamp: U256) -> Result<(), EvmError> {
-        self.executor.env_mut().block.timestamp = timestamp;
-        Ok(())
-    }
+            if storage.is_empty()
+                && before.balance == account.info.balance
+                && before.nonce == account.info.nonce
+                && before.code_hash == account.info.code_hash
+            {
+                continue;
+            }
 
-    pub fn get_block_timestamp(&self) -> U256 {
-        self.executor.env().block.timestamp
-    }
+            state_diff.insert(
+                *address,
+                AccountDiff {
+                    balance: ValueChange {
+                        before: before.balance,
+                        after: account.info.balance,
+                    },
+                    nonce: ValueChange {
+                        before: before.nonce,
+                        after: account.info.nonce,
+                    },
+                    code_hash: ValueChange {
+                        before: before.code_hash,
+                        after: account.info.code_hash,
+                    },
+                    storage,
+                },
+            );
+        }
 
-    pub fn get_chain_id(&self) -> u64 {
-        self.executor.env().cfg.chain_id
+        Ok(state_diff)
     }
 
-    fn set_access_list(&mut self, access_list: Option<AccessList>) -> Result<(), EvmError> {
-        if let Some(access_list) = access_list {
-            self.executor.env_mut().tx.access_list = access_list.into();
+    async fn decode_trace(
+        &mut self,
+        trace: Option<&mut CallTraceArena>,
+        format_trace: bool,
+        online: bool,
+    ) -> Result<Option<String>, EvmError> {
+        let Some(trace) = trace else {
+            return Ok(format_trace.then(String::new));
+        };
+
+        if online {
+            if let Some(identifier) = &mut self.etherscan_identifier {
+                self.decoder.identify(trace, identifier);
+            }
         }
 
-        Ok(())
+        // The decoder looks up unknown selectors with its signature identifier
+        let signature_identifier = if online {
+            None
+        } else {
+            self.decoder.signature_identifier.take()
+        };
+        let decoded = decode_trace_arena(trace, &self.decoder).await;
+        if signature_identifier.is_some() {
+            self.decoder.signature_identifier = signature_identifier;
+        }
+        decoded.map_err(|err| {
+            log::error!("Error decoding trace: {:?}", err);
+            EvmError(err.into())
+        })?;
+
+        if !format_trace {
+            return Ok(None);
+        }
+
+        let mut trace_writer = TraceWriter::new(Vec::<u8>::new());
+        trace_writer.write_arena(trace).map_err(|err| {
+            log::error!("Error writing trace: {:?}", err);
+            EvmError(err.into())
+        })?;
+
+        Ok(Some(
+            String::from_utf8(trace_writer.into_writer()).map_err(|err| {
+                log::error!("Error converting trace to string: {:?}", err);
+                EvmError(err.into())
+            })?,
+        ))
     }
 }
+
+/// Matches the authorizations of `call` against the code of their authorities after execution.
+/// An authorization is applied if its authority now delegates to the authorized address, or
+/// has no code when authorizing the zero address, which clears a delegation.
+fn delegations(call: &CallRawRequest, changeset: &EvmState) -> Vec<Delegation> {
+    call.authorization_list
+        .iter()
+        .flatten()
+        .map(|authorization| {
+            let authority = authorization.recover_authority().ok();
+            let code = authority
+                .and_then(|authority| changeset.get(&authority))
+                .and_then(|account| account.info.code.as_ref());
+            let applied = match code {
+                Some(Bytecode::Eip7702(code)) => code.delegated_address == authorization.address,
+                Some(code) => authorization.address.is_zero() && code.is_empty(),
+                None => false,
+            };
+
+            Delegation {
+                authority,
+                address: authorization.address,
+                nonce: authorization.nonce,
+                applied,
+            }
+        })
+        .collect()
+}
+
+/// revm depends on an older `alloy-eip7702` than alloy, convert between the two through the
+/// RLP encoding they share.
+fn revm_authorization(
+    authorization: &SignedAuthorization,
+) -> Result<revm_primitives::SignedAuthorization, InvalidAuthorizationError> {
+    let encoded = alloy::rlp::encode(authorization);
+    revm_primitives::SignedAuthorization::decode(&mut encoded.as_slice())
+        .map_err(|_| InvalidAuthorizationError())
+}
+
+fn add_abi(decoder: &mut CallTraceDecoder, abi: &JsonAbi) {
+    for function in abi.functions() {
+        decoder.push_function(function.clone());
+    }
+    for event in abi.events() {
+        decoder.push_event(event.clone());
+    }
+    for error in abi.errors() {
+        decoder.revert_decoder.push_error(error.clone());
+    }
+}
+
+fn etherscan_identifier(
+    etherscan_key: Option<String>,
+    chain_id: u64,
+    offline: bool,
+) -> Option<EtherscanIdentifier> {
+    let foundry_config = foundry_config::Config {
+        etherscan_api_key: etherscan_key,
+        offline,
+        ..Default::default()
+    };
+
+    let chain: Chain = chain_id.into();
+    EtherscanIdentifier::new(&foundry_config, Some(chain)).unwrap_or_default()
+}
@@ -1,22 +1,143 @@
+use alloy::primitives::U256;
 use dashmap::DashMap;
+use errors::TooManyStatefulSimulations;
 use evm::Evm;
 use serde::de::DeserializeOwned;
 use simulation::{SimulationRequest, StatefulSimulationRequest};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 use warp::{Filter, Rejection, Reply};
 
+pub mod assets;
 pub mod config;
 use config::Config;
 
+pub mod decode;
 pub mod errors;
 pub mod evm;
+pub mod replay;
+pub mod rpc;
 
 pub mod simulation;
+pub mod step_trace;
 
-pub struct SharedSimulationState {
-    pub evms: Arc<DashMap<Uuid, Arc<Mutex<Evm>>>>,
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct StatefulSimulation<E = Evm> {
+    pub evm: Arc<Mutex<E>>,
+    pub expires_at: SystemTime,
+}
+
+/// The stateful simulations being run, generic over the EVM so the bookkeeping can be used
+/// without a fork.
+pub struct SharedSimulationState<E = Evm> {
+    pub evms: Arc<DashMap<Uuid, StatefulSimulation<E>>>,
+    pub ttl: Duration,
+    pub max_stateful_simulations: Option<usize>,
+    /// Stateful simulations alive or being created, kept at or below the maximum
+    simulations: AtomicUsize,
+}
+
+/// A slot for a stateful simulation that is being created. The slot is given back if it is
+/// dropped before the simulation is inserted.
+pub struct Reservation<'a, E = Evm> {
+    state: &'a SharedSimulationState<E>,
+    filled: bool,
+}
+
+impl<E> Drop for Reservation<'_, E> {
+    fn drop(&mut self) {
+        if !self.filled {
+            self.state.simulations.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl SharedSimulationState {
+    pub fn new(config: &Config) -> Self {
+        SharedSimulationState {
+            evms: Arc::new(DashMap::new()),
+            ttl: Duration::from_secs(config.stateful_simulation_ttl),
+            max_stateful_simulations: config.max_stateful_simulations,
+            simulations: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<E> SharedSimulationState<E> {
+    /// Reserves a slot for a new stateful simulation, so the maximum can't be exceeded by
+    /// simulations being created at the same time.
+    pub fn reserve(&self) -> Result<Reservation<'_, E>, TooManyStatefulSimulations> {
+        let max = self.max_stateful_simulations.unwrap_or(usize::MAX);
+        self.simulations
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |simulations| {
+                (simulations < max).then_some(simulations + 1)
+            })
+            .map_err(|_| TooManyStatefulSimulations())?;
+
+        Ok(Reservation {
+            state: self,
+            filled: false,
+        })
+    }
+
+    /// Stores a new stateful simulation in a reserved slot, returning its id and when it
+    /// expires unless used.
+    pub fn insert_evm(&self, mut reservation: Reservation<'_, E>, evm: E) -> (Uuid, SystemTime) {
+        reservation.filled = true;
+
+        let id = Uuid::new_v4();
+        let expires_at = SystemTime::now() + self.ttl;
+        self.evms.insert(
+            id,
+            StatefulSimulation {
+                evm: Arc::new(Mutex::new(evm)),
+                expires_at,
+            },
+        );
+
+        (id, expires_at)
+    }
+
+    /// Looks up a stateful simulation, pushing back its expiry since it is being used.
+    pub fn get_evm(&self, id: &Uuid) -> Option<Arc<Mutex<E>>> {
+        let mut simulation = self.evms.get_mut(id)?;
+        simulation.expires_at = SystemTime::now() + self.ttl;
+        Some(simulation.evm.clone())
+    }
+
+    /// Removes a stateful simulation, returning whether it existed.
+    pub fn remove_evm(&self, id: &Uuid) -> bool {
+        let removed = self.evms.remove(id).is_some();
+        if removed {
+            self.simulations.fetch_sub(1, Ordering::SeqCst);
+        }
+        removed
+    }
+
+    pub fn remove_expired(&self) {
+        let now = SystemTime::now();
+        self.evms.retain(|id, simulation| {
+            let alive = simulation.expires_at > now;
+            if !alive {
+                log::info!(target: "ts::api", "Stateful simulation {id} expired");
+                self.simulations.fetch_sub(1, Ordering::SeqCst);
+            }
+            alive
+        });
+    }
+}
+
+/// Periodically drops stateful simulations that have not been used within their TTL.
+pub async fn remove_expired_simulations(state: Arc<SharedSimulationState>) {
+    let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        state.remove_expired();
+    }
 }
 
 pub fn simulate_routes(
@@ -24,9 +145,17 @@ pub fn simulate_routes(
     state: Arc<SharedSimulationState>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     simulate(config.clone())
+        .or(simulate_raw(config.clone()))
+        .or(estimate_gas(config.clone()))
+        .or(create_access_list(config.clone()))
         .or(simulate_bundle(config.clone()))
+        .or(replay(config.clone()))
         .or(simulate_stateful_new(config.clone(), state.clone()))
         .or(simulate_stateful_end(state.clone()))
+        .or(simulate_stateful_rpc(config.clone(), state.clone()))
+        .or(simulate_stateful_snapshot(state.clone()))
+        .or(simulate_stateful_revert(state.clone()))
+        .or(simulate_stateful_fork(state.clone()))
         .or(simulate_stateful(config, state))
 }
 
@@ -39,6 +168,39 @@ pub fn simulate(config: Config) -> impl Filter<Extract = (impl Reply,), Error =
         .and_then(simulation::simulate)
 }
 
+/// POST /simulate-raw
+pub fn simulate_raw(
+    config: Config,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("simulate-raw")
+        .and(warp::post())
+        .and(json_body(&config))
+        .and(with_config(config))
+        .and_then(simulation::simulate_raw)
+}
+
+/// POST /estimate-gas
+pub fn estimate_gas(
+    config: Config,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("estimate-gas")
+        .and(warp::post())
+        .and(json_body::<SimulationRequest>(&config))
+        .and(with_config(config))
+        .and_then(simulation::estimate_gas)
+}
+
+/// POST /create-access-list
+pub fn create_access_list(
+    config: Config,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("create-access-list")
+        .and(warp::post())
+        .and(json_body::<SimulationRequest>(&config))
+        .and(with_config(config))
+        .and_then(simulation::create_access_list)
+}
+
 /// POST /simulate-bundle
 pub fn simulate_bundle(
     config: Config,
@@ -50,6 +212,15 @@ pub fn simulate_bundle(
         .and_then(simulation::simulate_bundle)
 }
 
+/// POST /replay
+pub fn replay(config: Config) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("replay")
+        .and(warp::post())
+        .and(json_body(&config))
+        .and(with_config(config))
+        .and_then(replay::replay)
+}
+
 /// POST /simulate-stateful
 pub fn simulate_stateful_new(
     config: Config,
@@ -85,6 +256,48 @@ pub fn simulate_stateful(
         .and_then(simulation::simulate_stateful)
 }
 
+/// POST /simulate-stateful/{statefulSimulationId}/rpc
+pub fn simulate_stateful_rpc(
+    config: Config,
+    state: Arc<SharedSimulationState>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("simulate-stateful" / Uuid / "rpc")
+        .and(warp::post())
+        .and(json_body(&config))
+        .and(with_state(state))
+        .and_then(rpc::simulate_stateful_rpc)
+}
+
+/// POST /simulate-stateful/{statefulSimulationId}/snapshot
+pub fn simulate_stateful_snapshot(
+    state: Arc<SharedSimulationState>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("simulate-stateful" / Uuid / "snapshot")
+        .and(warp::post())
+        .and(with_state(state))
+        .and_then(simulation::simulate_stateful_snapshot)
+}
+
+/// POST /simulate-stateful/{statefulSimulationId}/revert/{snapshotId}
+pub fn simulate_stateful_revert(
+    state: Arc<SharedSimulationState>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("simulate-stateful" / Uuid / "revert" / U256)
+        .and(warp::post())
+        .and(with_state(state))
+        .and_then(simulation::simulate_stateful_revert)
+}
+
+/// POST /simulate-stateful/{statefulSimulationId}/fork
+pub fn simulate_stateful_fork(
+    state: Arc<SharedSimulationState>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    warp::path!("simulate-stateful" / Uuid / "fork")
+        .and(warp::post())
+        .and(with_state(state))
+        .and_then(simulation::simulate_stateful_fork)
+}
+
 fn with_config(
     config: Config,
 ) -> impl Filter<Extract = (Config,), Error = std::convert::Infallible> + Clone {
@@ -103,3 +316,73 @@ fn json_body<T: DeserializeOwned + Send>(
 ) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
     warp::body::content_length_limit(config.max_request_size).and(warp::body::json())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(ttl: Duration, max_stateful_simulations: Option<usize>) -> SharedSimulationState<()> {
+        SharedSimulationState {
+            evms: Arc::new(DashMap::new()),
+            ttl,
+            max_stateful_simulations,
+            simulations: AtomicUsize::new(0),
+        }
+    }
+
+    fn simulations<E>(state: &SharedSimulationState<E>) -> usize {
+        state.simulations.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn enforces_the_maximum() {
+        let state = state(Duration::from_secs(60), Some(2));
+
+        let first = state.reserve().unwrap();
+        let _second = state.reserve().unwrap();
+        assert!(state.reserve().is_err());
+
+        // Filling a slot keeps it taken
+        state.insert_evm(first, ());
+        assert!(state.reserve().is_err());
+        assert_eq!(simulations(&state), 2);
+    }
+
+    #[test]
+    fn gives_back_the_slot_of_a_dropped_reservation() {
+        let state = state(Duration::from_secs(60), Some(1));
+
+        drop(state.reserve().unwrap());
+
+        assert_eq!(simulations(&state), 0);
+        assert!(state.reserve().is_ok());
+    }
+
+    #[test]
+    fn ending_a_simulation_frees_its_slot_once() {
+        let state = state(Duration::from_secs(60), Some(2));
+        let (id, _) = state.insert_evm(state.reserve().unwrap(), ());
+        state.insert_evm(state.reserve().unwrap(), ());
+
+        assert!(state.remove_evm(&id));
+        assert!(!state.remove_evm(&id));
+
+        assert_eq!(simulations(&state), 1);
+    }
+
+    #[test]
+    fn expiry_frees_the_slots_of_expired_simulations_once() {
+        let state = state(Duration::from_secs(60), Some(2));
+        let (expired, _) = state.insert_evm(state.reserve().unwrap(), ());
+        let (alive, _) = state.insert_evm(state.reserve().unwrap(), ());
+        state.evms.get_mut(&expired).unwrap().expires_at = SystemTime::UNIX_EPOCH;
+
+        state.remove_expired();
+        state.remove_expired();
+
+        assert_eq!(simulations(&state), 1);
+        assert!(state.evms.contains_key(&alive));
+        assert!(!state.remove_evm(&expired));
+        assert_eq!(simulations(&state), 1);
+    }
+}
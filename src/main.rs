@@ -1,9 +1,9 @@
-use dashmap::DashMap;
 use std::{env, sync::Arc};
 use warp::Filter;
 
 use simulatoor::{
-    config::config, errors::handle_rejection, simulate_routes, SharedSimulationState,
+    config::config, errors::handle_rejection, remove_expired_simulations, simulate_routes,
+    SharedSimulationState,
 };
 
 #[tokio::main]
@@ -14,18 +14,31 @@ async fn main() {
     let config = config();
 
     let port = config.port;
-    let fork_url = config.fork_url.clone();
 
-    log::info!(
-        target: "ts::api",
-        "Forking from {fork_url}"
-    );
+    if let Some(fork_url) = &config.fork_url {
+        log::info!(
+            target: "ts::api",
+            "Forking from {fork_url}"
+        );
+    }
+    for (chain_id, fork_url) in &config.fork_urls {
+        log::info!(
+            target: "ts::api",
+            "Forking chain {chain_id} from {fork_url}"
+        );
+    }
+
+    if config.decoding.offline {
+        log::info!(
+            target: "ts::api",
+            "Offline, not looking up contracts on Etherscan or signatures online"
+        );
+    }
 
     let api_base = warp::path("api").and(warp::path("v1")).boxed();
 
-    let shared_state = Arc::new(SharedSimulationState {
-        evms: Arc::new(DashMap::new()),
-    });
+    let shared_state = Arc::new(SharedSimulationState::new(&config));
+    tokio::spawn(remove_expired_simulations(shared_state.clone()));
 
     let routes = api_base
         .and(simulate_routes(config, shared_state))
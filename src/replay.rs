@@ -0,0 +1,190 @@
+use alloy::consensus::Transaction;
+use alloy::json_abi::JsonAbi;
+use alloy::primitives::{Address, Bytes, B256, U256, U64};
+use alloy::providers::{Provider, ProviderBuilder};
+use alloy::rpc::types::{Block, Transaction as RpcTransaction};
+use alloy::transports::http::reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use warp::reject::Rejection;
+use warp::reply::Json;
+
+use crate::config::Config;
+use crate::errors::{
+    FailedSettingBlockNumberError, FailedSettingBlockTimestampError, ProviderError,
+    TransactionNotFoundError,
+};
+use crate::evm::CallRawRequest;
+use crate::simulation::{
+    new_evm, run, BlockOverrides, SimulationRequest, SimulationResponse, StateOverride,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayRequest {
+    pub chain_id: u64,
+    pub transaction_hash: B256,
+    /// Executes the transaction directly on top of the parent block, without the transactions
+    /// before it in its block, at the sender's nonce there
+    pub skip_preceding: Option<bool>,
+    /// Sends the transaction from a different account, at that account's current nonce
+    pub from: Option<Address>,
+    pub data: Option<Bytes>,
+    pub value: Option<U256>,
+    pub gas_limit: Option<u64>,
+    /// Applied after the preceding transactions, right before the replayed one
+    pub state_overrides: Option<HashMap<Address, StateOverride>>,
+    pub abis: Option<Vec<JsonAbi>>,
+    pub labels: Option<HashMap<Address, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayResponse {
+    #[serde(flatten)]
+    pub simulation: SimulationResponse,
+    pub transaction_index: u64,
+    /// Transactions of the same block executed before the replayed one
+    pub preceding_transactions: usize,
+    /// Preceding transactions that could not be executed, the replayed state may differ from
+    /// the chain if there are any
+    pub failed_preceding_transactions: usize,
+}
+
+/// Re-executes a mined transaction in the block it was mined in: on top of its parent block
+/// and, unless skipped, the transactions before it in the same block. The sender, calldata,
+/// value, gas limit and state can be overridden to see how a different transaction would have
+/// fared at the same position.
+pub async fn replay(request: ReplayRequest, config: Config) -> Result<Json, Rejection> {
+    let fork_url = config.fork_url_for(request.chain_id)?;
+    let provider = ProviderBuilder::new().on_http(
+        fork_url
+            .parse::<Url>()
+            .map_err(|err| ProviderError(eyre::Report::new(err)))?,
+    );
+
+    let transaction = provider
+        .get_transaction_by_hash(request.transaction_hash)
+        .await
+        .map_err(|err| ProviderError(err.into()))?
+        .ok_or(TransactionNotFoundError())?;
+
+    // Pending transactions have no block to replay them in
+    let (Some(block_number), Some(transaction_index)) =
+        (transaction.block_number, transaction.transaction_index)
+    else {
+        return Err(warp::reject::custom(TransactionNotFoundError()));
+    };
+
+    // The transactions are kept as JSON and decoded one by one, so a block with types that
+    // aren't Ethereum's, like the deposits every OP-stack block starts with, can be replayed
+    let block = provider
+        .raw_request::<_, Option<Block<Value>>>(
+            "eth_getBlockByNumber".into(),
+            (U64::from(block_number), true),
+        )
+        .await
+        .map_err(|err| ProviderError(err.into()))?
+        .ok_or(TransactionNotFoundError())?;
+
+    let mut evm = new_evm(
+        &config,
+        request.chain_id,
+        Some(block_number.saturating_sub(1)),
+        transaction.inner.gas_limit(),
+    )
+    .await?;
+
+    evm.set_block(U256::from(block_number))
+        .await
+        .map_err(|_| warp::reject::custom(FailedSettingBlockNumberError()))?;
+    evm.set_block_timestamp(U256::from(block.header.timestamp))
+        .await
+        .map_err(|_| warp::reject::custom(FailedSettingBlockTimestampError()))?;
+    evm.override_block(&BlockOverrides {
+        coinbase: Some(block.header.beneficiary),
+        base_fee: block.header.base_fee_per_gas.map(U256::from),
+        prevrandao: Some(block.header.mix_hash),
+        difficulty: Some(block.header.difficulty),
+        gas_limit: Some(block.header.gas_limit),
+        blob_base_fee: block.header.blob_fee().map(U256::from),
+        block_hash: None,
+    })?;
+
+    let skip_preceding = request.skip_preceding.unwrap_or(false);
+    let mut preceding_transactions = 0;
+    let mut failed_preceding_transactions = 0;
+    if !skip_preceding {
+        for (index, preceding) in block
+            .transactions
+            .txns()
+            .take(transaction_index as usize)
+            .enumerate()
+        {
+            preceding_transactions += 1;
+            let preceding = match serde_json::from_value::<RpcTransaction>(preceding.clone()) {
+                Ok(preceding) => preceding,
+                Err(err) => {
+                    log::warn!(
+                        "Skipping transaction {index} of block {block_number} of unsupported type: {err}"
+                    );
+                    failed_preceding_transactions += 1;
+                    continue;
+                }
+            };
+
+            let preceding_request = SimulationRequest::from_transaction(
+                &preceding.inner,
+                preceding.from,
+                request.chain_id,
+            );
+
+            // Only the state they leave behind matters, so they are not decoded
+            let call = CallRawRequest::try_from(&preceding_request)?;
+            if let Err(err) = evm.commit_raw(&call, preceding.inner.gas_limit()) {
+                log::warn!(
+                    "Error replaying transaction {}: {:?}",
+                    preceding.inner.tx_hash(),
+                    err
+                );
+                failed_preceding_transactions += 1;
+            }
+        }
+    }
+
+    let mut target =
+        SimulationRequest::from_transaction(&transaction.inner, transaction.from, request.chain_id);
+    // Without all the transactions before it, the sender may not have reached the nonce the
+    // transaction was sent with
+    if skip_preceding || failed_preceding_transactions > 0 {
+        target.nonce = None;
+    }
+    if let Some(from) = request.from {
+        target.from = from;
+        target.nonce = None;
+    }
+    if let Some(data) = request.data {
+        target.data = Some(data);
+    }
+    if let Some(value) = request.value {
+        target.value = Some(value);
+    }
+    if let Some(gas_limit) = request.gas_limit {
+        target.gas_limit = Some(gas_limit);
+    }
+    target.state_overrides = request.state_overrides;
+    target.abis = request.abis;
+    target.labels = request.labels;
+    target.format_trace = Some(true);
+    target.state_diff = Some(true);
+
+    let response = ReplayResponse {
+        simulation: run(&mut evm, target, true).await?,
+        transaction_index,
+        preceding_transactions,
+        failed_preceding_transactions,
+    };
+
+    Ok(warp::reply::json(&response))
+}
@@ -0,0 +1,224 @@
+use alloy::primitives::{keccak256, Address, Bytes, B256, U256, U64};
+use alloy::rpc::types::TransactionRequest;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+use warp::reject::Rejection;
+use warp::reply::Json;
+
+use crate::errors::{EvmError, StateNotFound};
+use crate::evm::{CallRawRequest, Evm, DEFAULT_GAS_CAP};
+use crate::SharedSimulationState;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    #[serde(default)]
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JsonRpcPayload {
+    Batch(Vec<JsonRpcRequest>),
+    Single(JsonRpcRequest),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    fn method_not_found(method: &str) -> Self {
+        JsonRpcError {
+            code: -32601,
+            message: format!("the method {method} does not exist/is not available"),
+            data: None,
+        }
+    }
+
+    fn invalid_params(err: serde_json::Error) -> Self {
+        JsonRpcError {
+            code: -32602,
+            message: format!("invalid params: {err}"),
+            data: None,
+        }
+    }
+
+    fn execution_reverted(return_data: Bytes) -> Self {
+        JsonRpcError {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: Some(Value::String(return_data.to_string())),
+        }
+    }
+}
+
+impl From<EvmError> for JsonRpcError {
+    fn from(err: EvmError) -> Self {
+        JsonRpcError {
+            code: -32000,
+            message: err.0.to_string(),
+            data: None,
+        }
+    }
+}
+
+impl From<TransactionRequest> for CallRawRequest {
+    fn from(transaction: TransactionRequest) -> Self {
+        CallRawRequest {
+            from: transaction.from.unwrap_or_default(),
+            to: transaction.to.and_then(|to| to.to().copied()),
+            salt: None,
+            value: transaction.value,
+            data: transaction.input.into_input(),
+            gas_price: transaction.gas_price.map(U256::from),
+            max_fee_per_gas: transaction.max_fee_per_gas.map(U256::from),
+            max_priority_fee_per_gas: transaction.max_priority_fee_per_gas.map(U256::from),
+            nonce: transaction.nonce,
+            access_list: transaction.access_list,
+            authorization_list: transaction.authorization_list,
+            blob_versioned_hashes: transaction.blob_versioned_hashes,
+            max_fee_per_blob_gas: transaction.max_fee_per_blob_gas.map(U256::from),
+            format_trace: false,
+            decode_online: false,
+            state_diff: false,
+        }
+    }
+}
+
+/// Serves the Ethereum JSON-RPC methods needed by ethers/viem/foundry against a stateful
+/// simulation. Block tags are accepted but ignored: every method runs against the current
+/// state of the simulation.
+pub async fn simulate_stateful_rpc(
+    param: Uuid,
+    payload: JsonRpcPayload,
+    state: Arc<SharedSimulationState>,
+) -> Result<Json, Rejection> {
+    let evm = state
+        .get_evm(&param)
+        .ok_or_else(|| warp::reject::custom(StateNotFound()))?;
+    let mut evm = evm.lock().await;
+
+    match payload {
+        JsonRpcPayload::Single(request) => Ok(warp::reply::json(&handle(&mut evm, request).await)),
+        JsonRpcPayload::Batch(requests) => {
+            let mut responses = Vec::with_capacity(requests.len());
+            for request in requests {
+                responses.push(handle(&mut evm, request).await);
+            }
+            Ok(warp::reply::json(&responses))
+        }
+    }
+}
+
+async fn handle(evm: &mut Evm, request: JsonRpcRequest) -> JsonRpcResponse {
+    let (result, error) = match dispatch(evm, &request.method, &request.params).await {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+
+    JsonRpcResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request.id,
+        result,
+        error,
+    }
+}
+
+async fn dispatch(evm: &mut Evm, method: &str, params: &[Value]) -> Result<Value, JsonRpcError> {
+    match method {
+        "eth_chainId" => to_value(U64::from(evm.get_chain_id())),
+        "eth_blockNumber" => to_value(evm.get_block()),
+        "eth_getBalance" => {
+            let address: Address = param(params, 0)?;
+            to_value(evm.get_balance(address)?)
+        }
+        "eth_getCode" => {
+            let address: Address = param(params, 0)?;
+            to_value(evm.get_code(address)?)
+        }
+        "eth_getStorageAt" => {
+            let address: Address = param(params, 0)?;
+            let slot: U256 = param(params, 1)?;
+            to_value(B256::from(evm.get_storage_at(address, slot)?))
+        }
+        "eth_getTransactionCount" => {
+            let address: Address = param(params, 0)?;
+            to_value(U64::from(evm.get_nonce(address)?))
+        }
+        "eth_call" => {
+            let transaction: TransactionRequest = param(params, 0)?;
+            let gas_limit = transaction.gas.unwrap_or(evm.get_gas_limit());
+            let res = evm.execute_raw(&transaction.into(), gas_limit)?;
+            if res.reverted {
+                return Err(JsonRpcError::execution_reverted(res.result));
+            }
+            to_value(res.result)
+        }
+        "eth_estimateGas" => {
+            let transaction: TransactionRequest = param(params, 0)?;
+            let gas_cap = transaction.gas.unwrap_or(DEFAULT_GAS_CAP);
+            let estimate = evm.estimate_gas(transaction.into(), gas_cap).await?;
+            match estimate.gas_limit {
+                Some(gas_limit) => to_value(U64::from(gas_limit)),
+                None => Err(JsonRpcError::execution_reverted(
+                    estimate.result.return_data,
+                )),
+            }
+        }
+        "eth_sendTransaction" => {
+            let transaction: TransactionRequest = param(params, 0)?;
+            let gas_limit = transaction.gas.unwrap_or(evm.get_gas_limit());
+            let call = CallRawRequest::from(transaction);
+
+            // Simulated transactions are never signed, so derive a stable pseudo hash from the
+            // sender and its nonce at the time of sending.
+            let nonce = evm.get_nonce(call.from)?;
+            let hash = keccak256([call.from.as_slice(), &nonce.to_be_bytes()].concat());
+
+            // There are no receipts to find out about a revert from, so a reverted transaction
+            // is an error and isn't committed
+            let res = evm.execute_raw(&call, gas_limit)?;
+            if res.reverted {
+                return Err(JsonRpcError::execution_reverted(res.result));
+            }
+            evm.commit(res.state_changeset);
+            to_value(hash)
+        }
+        _ => Err(JsonRpcError::method_not_found(method)),
+    }
+}
+
+fn param<T: DeserializeOwned>(params: &[Value], index: usize) -> Result<T, JsonRpcError> {
+    serde_json::from_value(params.get(index).cloned().unwrap_or_default())
+        .map_err(JsonRpcError::invalid_params)
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, JsonRpcError> {
+    serde_json::to_value(value).map_err(|err| JsonRpcError {
+        code: -32603,
+        message: err.to_string(),
+        data: None,
+    })
+}
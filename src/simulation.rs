@@ -1,40 +1,99 @@
-use alloy::primitives::{Address, U256};
-use dashmap::mapref::one::RefMut;
+use alloy::consensus::{Transaction, TxEnvelope};
+use alloy::eips::eip2718::Decodable2718;
+use alloy::eips::eip7702::SignedAuthorization;
+use alloy::json_abi::JsonAbi;
+use alloy::primitives::{Address, B256, U256};
+use alloy_rpc_types_trace::geth::{
+    GethDebugBuiltInTracerType, GethDebugTracerType, GethDebugTracingOptions, GethTrace,
+};
 use foundry_evm::traces::CallKind;
 use revm::interpreter::InstructionResult;
 use revm_primitives::{AccessList, Bytes, Log};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 use warp::reject::Rejection;
 use warp::reply::Json;
 
+use crate::assets::{asset_changes, AssetChanges};
+use crate::decode::{DecodedLog, RevertInfo};
 use crate::errors::{
     FailedSettingBlockNumberError, FailedSettingBlockTimestampError, IncorrectChainIdError,
-    InvalidBlockNumbersError, MultipleChainIdsError, NoBlockNumberError, StateNotFound,
+    InsufficientFundsError, InvalidBlockNumbersError, InvalidRawTransactionError,
+    InvalidTracerError, MultipleChainIdsError, NoBlockNumberError, NoGasLimitError,
+    SaltWithToError, SnapshotNotFound, StateNotFound,
 };
 use crate::evm::StorageOverride;
+use crate::step_trace::{StepTrace, StepTraceOptions};
 use crate::SharedSimulationState;
 
 use super::config::Config;
-use super::evm::{CallRawRequest, Evm};
+use super::evm::{CallRawRequest, Evm, GethTracer, DEFAULT_GAS_CAP};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SimulationRequest {
     pub chain_id: u64,
     pub from: Address,
-    pub to: Address,
+    pub to: Option<Address>,
+    pub salt: Option<B256>,
     pub data: Option<Bytes>,
-    pub gas_limit: u64,
+    pub gas_limit: Option<u64>,
     pub value: Option<U256>,
+    pub gas_price: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub nonce: Option<u64>,
     pub access_list: Option<AccessList>,
+    /// EIP-7702 authorizations, delegating the code of their signers for this transaction
+    pub authorization_list: Option<Vec<SignedAuthorization>>,
+    /// EIP-4844 blob hashes, returned by the `BLOBHASH` opcode
+    pub blob_versioned_hashes: Option<Vec<B256>>,
+    /// Defaults to the current blob base fee
+    pub max_fee_per_blob_gas: Option<U256>,
+    pub block_number: Option<u64>,
+    pub block_timestamp: Option<U256>,
+    /// Milliseconds between blocks when this transaction advances the block number
+    pub block_time_ms: Option<u64>,
+    pub state_overrides: Option<HashMap<Address, StateOverride>>,
+    pub block_overrides: Option<BlockOverrides>,
+    pub format_trace: Option<bool>,
+    /// Look up contracts on Etherscan and selectors in the signature database to decode the
+    /// trace, logs and revert with. Otherwise only supplied and already identified ABIs are
+    /// used. Implied by `formatTrace`
+    pub decode_online: Option<bool>,
+    pub state_diff: Option<bool>,
+    /// Also trace with a geth tracer, using the options of geth's `debug_traceCall`
+    pub geth_trace: Option<GethDebugTracingOptions>,
+    /// Also record every executed opcode
+    pub step_trace: Option<StepTraceOptions>,
+    /// ABIs to decode the trace, logs and revert with, e.g. of unverified contracts. Their
+    /// functions, events and errors are matched by selector wherever they appear
+    pub abis: Option<Vec<JsonAbi>>,
+    /// Names to show contracts under in the trace and logs
+    pub labels: Option<HashMap<Address, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawSimulationRequest {
+    /// Defaults to the chain id the transaction was signed for
+    pub chain_id: Option<u64>,
+    /// EIP-2718 encoded signed transaction
+    pub raw_transaction: Bytes,
     pub block_number: Option<u64>,
     pub block_timestamp: Option<U256>,
     pub state_overrides: Option<HashMap<Address, StateOverride>>,
+    pub block_overrides: Option<BlockOverrides>,
     pub format_trace: Option<bool>,
+    pub decode_online: Option<bool>,
+    pub state_diff: Option<bool>,
+    pub geth_trace: Option<GethDebugTracingOptions>,
+    pub step_trace: Option<StepTraceOptions>,
+    pub abis: Option<Vec<JsonAbi>>,
+    pub labels: Option<HashMap<Address, String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -42,15 +101,80 @@ pub struct SimulationRequest {
 pub struct SimulationResponse {
     pub simulation_id: u64,
     pub gas_used: u64,
+    pub effective_gas_price: U256,
+    pub total_fee: U256,
     pub block_number: u64,
     pub success: bool,
     pub trace: Vec<CallTrace>,
     pub formatted_trace: Option<String>,
+    pub geth_trace: Option<GethTrace>,
+    pub step_trace: Option<StepTrace>,
     pub logs: Vec<Log>,
+    pub decoded_logs: Vec<DecodedLog>,
+    pub exit_reason: InstructionResult,
+    pub return_data: Bytes,
+    pub revert: Option<RevertInfo>,
+    pub deployment: Option<Deployment>,
+    pub delegations: Vec<Delegation>,
+    pub state_diff: Option<HashMap<Address, AccountDiff>>,
+    pub asset_changes: AssetChanges,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GasEstimationResponse {
+    /// The lowest gas limit the call succeeds with, `None` if it fails even at the cap
+    pub gas_limit: Option<u64>,
+    pub gas_used: u64,
+    pub success: bool,
     pub exit_reason: InstructionResult,
     pub return_data: Bytes,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListResponse {
+    pub access_list: AccessList,
+    /// Gas used with the generated access list
+    pub gas_used: u64,
+    pub gas_used_without_access_list: u64,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Deployment {
+    pub address: Address,
+    pub code_size: usize,
+    pub gas_used: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Delegation {
+    /// The signer of the authorization, `None` if the signature is invalid
+    pub authority: Option<Address>,
+    pub address: Address,
+    pub nonce: u64,
+    /// Whether the authority's code delegates to `address` after execution
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    pub balance: ValueChange<U256>,
+    pub nonce: ValueChange<u64>,
+    pub code_hash: ValueChange<B256>,
+    pub storage: HashMap<U256, ValueChange<U256>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ValueChange<T> {
+    pub before: T,
+    pub after: T,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StatefulSimulationRequest {
@@ -58,12 +182,17 @@ pub struct StatefulSimulationRequest {
     pub gas_limit: u64,
     pub block_number: Option<u64>,
     pub block_timestamp: Option<U256>,
+    /// Milliseconds between blocks, defaults to the configured block time of the chain
+    pub block_time_ms: Option<u64>,
+    pub block_overrides: Option<BlockOverrides>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct StatefulSimulationResponse {
     pub stateful_simulation_id: Uuid,
+    /// Unix timestamp after which the simulation is removed unless it is used again
+    pub expires_at: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -71,6 +200,17 @@ pub struct StatefulSimulationEndResponse {
     pub success: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StatefulSimulationSnapshotResponse {
+    pub snapshot_id: U256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatefulSimulationRevertResponse {
+    pub success: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StateOverride {
     pub balance: Option<U256>,
@@ -109,47 +249,224 @@ impl From<State> for StorageOverride {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockOverrides {
+    pub coinbase: Option<Address>,
+    pub base_fee: Option<U256>,
+    /// Post-merge randomness, returned by the `PREVRANDAO` opcode
+    pub prevrandao: Option<B256>,
+    /// Pre-merge difficulty, returned by the `DIFFICULTY` opcode on forks before the merge
+    pub difficulty: Option<U256>,
+    pub gas_limit: Option<u64>,
+    pub blob_base_fee: Option<U256>,
+    /// Hashes returned by the `BLOCKHASH` opcode, by block number
+    pub block_hash: Option<HashMap<u64, B256>>,
+}
+
+impl TryFrom<GethDebugTracingOptions> for GethTracer {
+    type Error = InvalidTracerError;
+
+    fn try_from(options: GethDebugTracingOptions) -> Result<Self, Self::Error> {
+        let Some(tracer) = options.tracer else {
+            return Ok(GethTracer::StructLogger(options.config));
+        };
+
+        let config = options.tracer_config;
+        match tracer {
+            GethDebugTracerType::BuiltInTracer(GethDebugBuiltInTracerType::CallTracer) => config
+                .into_call_config()
+                .map(GethTracer::Call)
+                .map_err(|_| InvalidTracerError()),
+            GethDebugTracerType::BuiltInTracer(GethDebugBuiltInTracerType::PreStateTracer) => {
+                config
+                    .into_pre_state_config()
+                    .map(GethTracer::PreState)
+                    .map_err(|_| InvalidTracerError())
+            }
+            GethDebugTracerType::BuiltInTracer(GethDebugBuiltInTracerType::FourByteTracer) => {
+                Ok(GethTracer::FourByte)
+            }
+            GethDebugTracerType::BuiltInTracer(GethDebugBuiltInTracerType::NoopTracer) => {
+                Ok(GethTracer::Noop)
+            }
+            // JavaScript, mux and flat call tracers are not supported
+            _ => Err(InvalidTracerError()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct CallTrace {
+    pub index: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub depth: usize,
     pub call_type: CallKind,
     pub from: Address,
     pub to: Address,
     pub value: U256,
+    pub input: Bytes,
+    pub output: Bytes,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub success: bool,
+    pub status: InstructionResult,
+    pub label: Option<String>,
+    pub function: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub decoded_output: Option<String>,
 }
 
-async fn run(
-    evm: &mut Evm,
-    transaction: SimulationRequest,
-    commit: bool,
-) -> Result<SimulationResponse, Rejection> {
-    for (address, state_override) in transaction.state_overrides.into_iter().flatten() {
+impl SimulationRequest {
+    /// Builds the request that executes an existing transaction as sent by `from`.
+    pub fn from_transaction<T: Transaction>(transaction: &T, from: Address, chain_id: u64) -> Self {
+        let (gas_price, max_fee_per_gas, max_priority_fee_per_gas) =
+            match transaction.max_priority_fee_per_gas() {
+                Some(priority_fee) => (
+                    None,
+                    Some(U256::from(transaction.max_fee_per_gas())),
+                    Some(U256::from(priority_fee)),
+                ),
+                None => (transaction.gas_price().map(U256::from), None, None),
+            };
+
+        SimulationRequest {
+            chain_id,
+            from,
+            to: transaction.to(),
+            salt: None,
+            data: Some(Bytes::copy_from_slice(transaction.input())),
+            gas_limit: Some(transaction.gas_limit()),
+            value: Some(transaction.value()),
+            gas_price,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            nonce: Some(transaction.nonce()),
+            access_list: transaction.access_list().cloned(),
+            authorization_list: transaction.authorization_list().map(<[_]>::to_vec),
+            blob_versioned_hashes: transaction.blob_versioned_hashes().map(<[_]>::to_vec),
+            max_fee_per_blob_gas: transaction.max_fee_per_blob_gas().map(U256::from),
+            block_number: None,
+            block_timestamp: None,
+            block_time_ms: None,
+            state_overrides: None,
+            block_overrides: None,
+            format_trace: None,
+            decode_online: None,
+            state_diff: None,
+            geth_trace: None,
+            step_trace: None,
+            abis: None,
+            labels: None,
+        }
+    }
+}
+
+impl TryFrom<&SimulationRequest> for CallRawRequest {
+    type Error = SaltWithToError;
+
+    fn try_from(transaction: &SimulationRequest) -> Result<Self, Self::Error> {
+        if transaction.to.is_some() && transaction.salt.is_some() {
+            return Err(SaltWithToError());
+        }
+
+        Ok(CallRawRequest {
+            from: transaction.from,
+            to: transaction.to,
+            salt: transaction.salt,
+            value: transaction.value,
+            data: transaction.data.clone(),
+            gas_price: transaction.gas_price,
+            max_fee_per_gas: transaction.max_fee_per_gas,
+            max_priority_fee_per_gas: transaction.max_priority_fee_per_gas,
+            nonce: transaction.nonce,
+            access_list: transaction.access_list.clone(),
+            authorization_list: transaction.authorization_list.clone(),
+            blob_versioned_hashes: transaction.blob_versioned_hashes.clone(),
+            max_fee_per_blob_gas: transaction.max_fee_per_blob_gas,
+            format_trace: transaction.format_trace.unwrap_or(false),
+            decode_online: transaction.decode_online.unwrap_or(false),
+            state_diff: transaction.state_diff.unwrap_or(false),
+        })
+    }
+}
+
+fn apply_overrides(evm: &mut Evm, transaction: &SimulationRequest) -> Result<(), Rejection> {
+    if let Some(block_overrides) = &transaction.block_overrides {
+        evm.override_block(block_overrides)?;
+    }
+
+    for (address, state_override) in transaction.state_overrides.iter().flatten() {
         evm.override_account(
-            address,
+            *address,
             state_override.balance.map(U256::from),
             state_override.nonce,
-            state_override.code,
-            state_override.state.map(StorageOverride::from),
+            state_override.code.clone(),
+            state_override.state.clone().map(StorageOverride::from),
         )?;
     }
 
-    let call = CallRawRequest {
-        from: transaction.from,
-        to: transaction.to,
-        value: transaction.value,
-        data: transaction.data,
-        access_list: transaction.access_list,
-        format_trace: transaction.format_trace.unwrap_or(false),
+    Ok(())
+}
+
+pub(crate) async fn run(
+    evm: &mut Evm,
+    transaction: SimulationRequest,
+    commit: bool,
+) -> Result<SimulationResponse, Rejection> {
+    let call = CallRawRequest::try_from(&transaction)?;
+    let gas_limit = transaction.gas_limit.unwrap_or(evm.get_gas_limit());
+
+    // Checked before applying the overrides, which a stateful simulation would otherwise keep
+    // even though the transaction is rejected
+    let balance_override = transaction
+        .state_overrides
+        .as_ref()
+        .and_then(|state_overrides| state_overrides.get(&call.from))
+        .and_then(|state_override| state_override.balance);
+    let balance = match balance_override {
+        Some(balance) => balance,
+        None => evm.get_balance(call.from)?,
+    };
+    if balance < evm.max_cost(&call, gas_limit, transaction.block_overrides.as_ref()) {
+        return Err(warp::reject::custom(InsufficientFundsError()));
+    }
+
+    apply_overrides(evm, &transaction)?;
+    add_contracts(
+        evm,
+        transaction.abis.as_deref(),
+        transaction.labels.as_ref(),
+    );
+
+    // Traced before executing, so a committed transaction is traced on the same state
+    let geth_trace = match transaction.geth_trace {
+        Some(options) => {
+            let tracer = GethTracer::try_from(options)?;
+            Some(evm.geth_trace(&call, gas_limit, tracer)?)
+        }
+        None => None,
+    };
+    let step_trace = match &transaction.step_trace {
+        Some(options) => Some(evm.step_trace(&call, gas_limit, options)?),
+        None => None,
     };
+
     let result = if commit {
-        evm.transact_raw(call, transaction.gas_limit).await?
+        evm.transact_raw(call, gas_limit).await?
     } else {
         evm.call_raw(call).await?
     };
 
+    let asset_changes = asset_changes(&result.logs, result.trace.as_ref());
+
     Ok(SimulationResponse {
         simulation_id: 1,
         gas_used: result.gas_used,
+        effective_gas_price: result.effective_gas_price,
+        total_fee: result.total_fee,
         block_number: result.block_number,
         success: result.success,
         trace: result
@@ -160,26 +477,70 @@ async fn run(
             .map(CallTrace::from)
             .collect(),
         logs: result.logs,
+        decoded_logs: result.decoded_logs,
         exit_reason: result.exit_reason,
         formatted_trace: result.formatted_trace,
+        geth_trace,
+        step_trace,
         return_data: result.return_data,
+        revert: result.revert,
+        deployment: result.deployment,
+        delegations: result.delegations,
+        state_diff: result.state_diff,
+        asset_changes,
     })
 }
 
-pub async fn simulate(transaction: SimulationRequest, config: Config) -> Result<Json, Rejection> {
-    let fork_url = config.fork_url;
+pub(crate) async fn new_evm(
+    config: &Config,
+    chain_id: u64,
+    block_number: Option<u64>,
+    gas_limit: u64,
+) -> Result<Evm, Rejection> {
+    let fork_url = config.fork_url_for(chain_id)?;
     let mut evm = Evm::new(
         None,
         fork_url,
-        transaction.block_number,
-        transaction.gas_limit,
-        config.etherscan_key,
+        block_number,
+        gas_limit,
+        config.decoding.clone(),
     )
     .await?;
 
-    if evm.get_chain_id() != transaction.chain_id {
+    if evm.get_chain_id() != chain_id {
         return Err(warp::reject::custom(IncorrectChainIdError()));
     }
+    evm.set_block_time_ms(config.block_time_ms_for(chain_id));
+    add_contracts(
+        &mut evm,
+        config.abis.get(&chain_id).map(Vec::as_slice),
+        config.labels.get(&chain_id),
+    );
+
+    Ok(evm)
+}
+
+fn add_contracts(
+    evm: &mut Evm,
+    abis: Option<&[JsonAbi]>,
+    labels: Option<&HashMap<Address, String>>,
+) {
+    for abi in abis.into_iter().flatten() {
+        evm.add_abi(abi);
+    }
+    for (address, label) in labels.into_iter().flatten() {
+        evm.add_label(*address, label.clone());
+    }
+}
+
+pub async fn simulate(transaction: SimulationRequest, config: Config) -> Result<Json, Rejection> {
+    let mut evm = new_evm(
+        &config,
+        transaction.chain_id,
+        transaction.block_number,
+        transaction.gas_limit.ok_or(NoGasLimitError())?,
+    )
+    .await?;
 
     if let Some(timestamp) = transaction.block_timestamp {
         evm.set_block_timestamp(U256::from(timestamp))
@@ -192,35 +553,126 @@ pub async fn simulate(transaction: SimulationRequest, config: Config) -> Result<
     Ok(warp::reply::json(&response))
 }
 
-pub async fn simulate_bundle(
-    transactions: Vec<SimulationRequest>,
+/// Simulates a signed transaction, with the sender recovered from its signature.
+pub async fn simulate_raw(
+    request: RawSimulationRequest,
     config: Config,
 ) -> Result<Json, Rejection> {
-    let first_chain_id = transactions[0].chain_id;
-    let first_block_number = transactions[0].block_number;
-    let first_block_timestamp = transactions[0].block_timestamp;
+    let envelope = TxEnvelope::decode_2718(&mut request.raw_transaction.as_ref())
+        .map_err(|_| warp::reject::custom(InvalidRawTransactionError()))?;
+    let from = envelope
+        .recover_signer()
+        .map_err(|_| warp::reject::custom(InvalidRawTransactionError()))?;
+
+    let chain_id = match (request.chain_id, envelope.chain_id()) {
+        (Some(chain_id), Some(signed_chain_id)) if chain_id != signed_chain_id => {
+            return Err(warp::reject::custom(IncorrectChainIdError()));
+        }
+        (Some(chain_id), _) | (None, Some(chain_id)) => chain_id,
+        (None, None) => return Err(warp::reject::custom(InvalidRawTransactionError())),
+    };
 
-    let fork_url = config.fork_url;
+    let transaction = SimulationRequest {
+        block_number: request.block_number,
+        block_timestamp: request.block_timestamp,
+        state_overrides: request.state_overrides,
+        block_overrides: request.block_overrides,
+        format_trace: request.format_trace,
+        decode_online: request.decode_online,
+        state_diff: request.state_diff,
+        geth_trace: request.geth_trace,
+        step_trace: request.step_trace,
+        abis: request.abis,
+        labels: request.labels,
+        ..SimulationRequest::from_transaction(&envelope, from, chain_id)
+    };
 
-    let mut evm = Evm::new(
-        None,
-        fork_url,
-        first_block_number,
-        transactions[0].gas_limit,
-        config.etherscan_key,
+    simulate(transaction, config).await
+}
+
+pub async fn estimate_gas(
+    transaction: SimulationRequest,
+    config: Config,
+) -> Result<Json, Rejection> {
+    let gas_cap = transaction.gas_limit.unwrap_or(DEFAULT_GAS_CAP);
+    let mut evm = new_evm(
+        &config,
+        transaction.chain_id,
+        transaction.block_number,
+        gas_cap,
     )
     .await?;
 
-    if evm.get_chain_id() != first_chain_id {
-        return Err(warp::reject::custom(IncorrectChainIdError()));
+    if let Some(timestamp) = transaction.block_timestamp {
+        evm.set_block_timestamp(timestamp)
+            .await
+            .map_err(|_| warp::reject::custom(FailedSettingBlockTimestampError()))?;
     }
 
-    if let Some(timestamp) = first_block_timestamp {
+    let call = CallRawRequest::try_from(&transaction)?;
+    apply_overrides(&mut evm, &transaction)?;
+
+    let estimate = evm.estimate_gas(call, gas_cap).await?;
+
+    let response = GasEstimationResponse {
+        gas_limit: estimate.gas_limit,
+        gas_used: estimate.result.gas_used,
+        success: estimate.result.success,
+        exit_reason: estimate.result.exit_reason,
+        return_data: estimate.result.return_data,
+    };
+
+    Ok(warp::reply::json(&response))
+}
+
+pub async fn create_access_list(
+    transaction: SimulationRequest,
+    config: Config,
+) -> Result<Json, Rejection> {
+    let mut evm = new_evm(
+        &config,
+        transaction.chain_id,
+        transaction.block_number,
+        transaction.gas_limit.unwrap_or(DEFAULT_GAS_CAP),
+    )
+    .await?;
+
+    if let Some(timestamp) = transaction.block_timestamp {
         evm.set_block_timestamp(timestamp)
             .await
             .map_err(|_| warp::reject::custom(FailedSettingBlockTimestampError()))?;
     }
 
+    let call = CallRawRequest::try_from(&transaction)?;
+    apply_overrides(&mut evm, &transaction)?;
+
+    let result = evm.create_access_list(&call)?;
+
+    let response = AccessListResponse {
+        access_list: result.access_list,
+        gas_used: result.gas_used,
+        gas_used_without_access_list: result.gas_used_without_access_list,
+        success: result.success,
+    };
+
+    Ok(warp::reply::json(&response))
+}
+
+pub async fn simulate_bundle(
+    transactions: Vec<SimulationRequest>,
+    config: Config,
+) -> Result<Json, Rejection> {
+    let first_chain_id = transactions[0].chain_id;
+    let first_block_number = transactions[0].block_number;
+
+    let mut evm = new_evm(
+        &config,
+        first_chain_id,
+        first_block_number,
+        transactions[0].gas_limit.ok_or(NoGasLimitError())?,
+    )
+    .await?;
+
     let mut response = Vec::with_capacity(transactions.len());
     for transaction in transactions {
         if transaction.chain_id != first_chain_id {
@@ -237,11 +689,13 @@ pub async fn simulate_bundle(
                 return Err(warp::reject::custom(InvalidBlockNumbersError()));
             }
 
-            evm.set_block(tx_block)
+            evm.advance_block(tx_block, transaction.block_time_ms)
                 .await
                 .map_err(|_| warp::reject::custom(FailedSettingBlockNumberError()))?;
+        }
 
-            evm.set_block_timestamp(evm.get_block_timestamp() + U256::from(12)) // TOOD: make block time configurable
+        if let Some(timestamp) = transaction.block_timestamp {
+            evm.set_block_timestamp(timestamp)
                 .await
                 .map_err(|_| warp::reject::custom(FailedSettingBlockTimestampError()))?;
         }
@@ -257,13 +711,12 @@ pub async fn simulate_stateful_new(
     config: Config,
     state: Arc<SharedSimulationState>,
 ) -> Result<Json, Rejection> {
-    let fork_url = config.fork_url;
-    let mut evm = Evm::new(
-        None,
-        fork_url,
+    let reservation = state.reserve()?;
+    let mut evm = new_evm(
+        &config,
+        stateful_simulation_request.chain_id,
         stateful_simulation_request.block_number,
         stateful_simulation_request.gas_limit,
-        config.etherscan_key,
     )
     .await?;
 
@@ -273,11 +726,19 @@ pub async fn simulate_stateful_new(
             .map_err(|_| warp::reject::custom(FailedSettingBlockTimestampError()))?;
     }
 
-    let new_id = Uuid::new_v4();
-    state.evms.insert(new_id, Arc::new(Mutex::new(evm)));
+    if let Some(block_time_ms) = stateful_simulation_request.block_time_ms {
+        evm.set_block_time_ms(block_time_ms);
+    }
+
+    if let Some(block_overrides) = &stateful_simulation_request.block_overrides {
+        evm.override_block(block_overrides)?;
+    }
+
+    let (new_id, expires_at) = state.insert_evm(reservation, evm);
 
     let response = StatefulSimulationResponse {
         stateful_simulation_id: new_id,
+        expires_at: unix_timestamp(expires_at),
     };
 
     Ok(warp::reply::json(&response))
@@ -287,8 +748,7 @@ pub async fn simulate_stateful_end(
     param: Uuid,
     state: Arc<SharedSimulationState>,
 ) -> Result<Json, Rejection> {
-    if state.evms.contains_key(&param) {
-        state.evms.remove(&param);
+    if state.remove_evm(&param) {
         let response = StatefulSimulationEndResponse { success: true };
         Ok(warp::reply::json(&response))
     } else {
@@ -306,14 +766,9 @@ pub async fn simulate_stateful(
 
     let mut response = Vec::with_capacity(transactions.len());
 
-    // Get a mutable reference to the EVM here.
-    let evm_ref_mut: RefMut<'_, Uuid, Arc<Mutex<Evm>>> = state
-        .evms
-        .get_mut(&param)
-        .ok_or_else(warp::reject::not_found)?;
-
-    // Dereference to obtain the EVM.
-    let evm = evm_ref_mut.value();
+    let evm = state
+        .get_evm(&param)
+        .ok_or_else(|| warp::reject::custom(StateNotFound()))?;
     let mut evm = evm.lock().await;
 
     if evm.get_chain_id() != first_chain_id {
@@ -337,12 +792,13 @@ pub async fn simulate_stateful(
                 return Err(warp::reject::custom(InvalidBlockNumbersError()));
             }
 
-            evm.set_block(tx_block)
+            evm.advance_block(tx_block, transaction.block_time_ms)
                 .await
                 .map_err(|_| warp::reject::custom(FailedSettingBlockNumberError()))?;
+        }
 
-            let block_timestamp = evm.get_block_timestamp();
-            evm.set_block_timestamp(block_timestamp + U256::from(12)) // TOOD: make block time configurable
+        if let Some(timestamp) = transaction.block_timestamp {
+            evm.set_block_timestamp(timestamp)
                 .await
                 .map_err(|_| warp::reject::custom(FailedSettingBlockTimestampError()))?;
         }
@@ -352,3 +808,110 @@ pub async fn simulate_stateful(
 
     Ok(warp::reply::json(&response))
 }
+
+pub async fn simulate_stateful_fork(
+    param: Uuid,
+    state: Arc<SharedSimulationState>,
+) -> Result<Json, Rejection> {
+    let evm = state
+        .get_evm(&param)
+        .ok_or_else(|| warp::reject::custom(StateNotFound()))?;
+    let reservation = state.reserve()?;
+    let forked_evm = evm.lock().await.fork();
+
+    let (new_id, expires_at) = state.insert_evm(reservation, forked_evm);
+
+    let response = StatefulSimulationResponse {
+        stateful_simulation_id: new_id,
+        expires_at: unix_timestamp(expires_at),
+    };
+
+    Ok(warp::reply::json(&response))
+}
+
+pub async fn simulate_stateful_snapshot(
+    param: Uuid,
+    state: Arc<SharedSimulationState>,
+) -> Result<Json, Rejection> {
+    let evm = state
+        .get_evm(&param)
+        .ok_or_else(|| warp::reject::custom(StateNotFound()))?;
+    let snapshot_id = evm.lock().await.snapshot();
+
+    let response = StatefulSimulationSnapshotResponse { snapshot_id };
+
+    Ok(warp::reply::json(&response))
+}
+
+pub async fn simulate_stateful_revert(
+    param: Uuid,
+    snapshot_id: U256,
+    state: Arc<SharedSimulationState>,
+) -> Result<Json, Rejection> {
+    let evm = state
+        .get_evm(&param)
+        .ok_or_else(|| warp::reject::custom(StateNotFound()))?;
+
+    if !evm.lock().await.revert_to_snapshot(snapshot_id) {
+        return Err(warp::reject::custom(SnapshotNotFound()));
+    }
+
+    let response = StatefulSimulationRevertResponse { success: true };
+
+    Ok(warp::reply::json(&response))
+}
+
+fn unix_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tracer(options: serde_json::Value) -> Result<GethTracer, InvalidTracerError> {
+        GethTracer::try_from(serde_json::from_value::<GethDebugTracingOptions>(options).unwrap())
+    }
+
+    #[test]
+    fn defaults_to_the_struct_logger() {
+        assert!(matches!(
+            tracer(json!({"enableMemory": true})),
+            Ok(GethTracer::StructLogger(config)) if config.enable_memory == Some(true)
+        ));
+    }
+
+    #[test]
+    fn reads_the_config_of_builtin_tracers() {
+        assert!(matches!(
+            tracer(json!({"tracer": "callTracer", "tracerConfig": {"onlyTopCall": true}})),
+            Ok(GethTracer::Call(config)) if config.only_top_call == Some(true)
+        ));
+        assert!(matches!(
+            tracer(json!({"tracer": "prestateTracer", "tracerConfig": {"diffMode": true}})),
+            Ok(GethTracer::PreState(config)) if config.diff_mode == Some(true)
+        ));
+        assert!(matches!(
+            tracer(json!({"tracer": "4byteTracer"})),
+            Ok(GethTracer::FourByte)
+        ));
+        assert!(matches!(
+            tracer(json!({"tracer": "noopTracer"})),
+            Ok(GethTracer::Noop)
+        ));
+    }
+
+    #[test]
+    fn rejects_unsupported_tracers_and_invalid_configs() {
+        assert!(tracer(json!({"tracer": "flatCallTracer"})).is_err());
+        assert!(tracer(json!({"tracer": "{ result: function() { return 1; } }"})).is_err());
+        assert!(tracer(json!({
+            "tracer": "callTracer",
+            "tracerConfig": {"onlyTopCall": "yes"}
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use alloy::primitives::{Bytes, U256};
+use revm::interpreter::{opcode, Interpreter, OpCode};
+use revm::{Database, EvmContext, Inspector};
+use serde::{Deserialize, Serialize};
+
+/// Most steps recorded for a single transaction, whatever the request asks for.
+pub const MAX_STEPS: usize = 100_000;
+
+/// Most bytes of stack and memory recorded for a single transaction, roughly the size of the
+/// serialized trace.
+pub const MAX_PAYLOAD_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StepTraceOptions {
+    /// Record the memory of the current frame at every step
+    pub memory: Option<bool>,
+    /// Record the slot and value of every `SLOAD` and `SSTORE`
+    pub storage: Option<bool>,
+    /// Defaults to, and is capped at, [`MAX_STEPS`]
+    pub max_steps: Option<usize>,
+    /// Defaults to, and is capped at, [`MAX_PAYLOAD_BYTES`]
+    pub max_payload_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StepTrace {
+    pub steps: Vec<Step>,
+    /// Whether steps were left out because a limit was reached
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    pub pc: usize,
+    pub op: String,
+    /// Gas left before the opcode executes
+    pub gas: u64,
+    pub gas_cost: u64,
+    pub depth: u64,
+    pub stack: Vec<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Bytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<StorageAccess>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StorageAccess {
+    pub slot: U256,
+    /// The value read by `SLOAD` or written by `SSTORE`
+    pub value: U256,
+}
+
+/// Records every executed opcode until a step or payload limit is reached.
+#[derive(Debug, Default)]
+pub struct StepTracer {
+    memory: bool,
+    storage: bool,
+    max_steps: usize,
+    max_payload_bytes: usize,
+    payload_bytes: usize,
+    trace: StepTrace,
+    /// The opcode of the last `step` if it was recorded, so `step_end` can complete it
+    recorded_op: Option<u8>,
+}
+
+impl StepTracer {
+    pub fn new(options: &StepTraceOptions) -> Self {
+        StepTracer {
+            memory: options.memory.unwrap_or(false),
+            storage: options.storage.unwrap_or(false),
+            max_steps: options.max_steps.unwrap_or(MAX_STEPS).min(MAX_STEPS),
+            max_payload_bytes: options
+                .max_payload_bytes
+                .unwrap_or(MAX_PAYLOAD_BYTES)
+                .min(MAX_PAYLOAD_BYTES),
+            ..Default::default()
+        }
+    }
+
+    pub fn into_trace(self) -> StepTrace {
+        self.trace
+    }
+}
+
+impl<DB: Database> Inspector<DB> for StepTracer {
+    fn step(&mut self, interp: &mut Interpreter, context: &mut EvmContext<DB>) {
+        self.recorded_op = None;
+        if self.trace.truncated {
+            return;
+        }
+
+        let stack = interp.stack.data().clone();
+        let memory = self
+            .memory
+            .then(|| Bytes::copy_from_slice(interp.shared_memory.context_memory()));
+
+        // Hex encoded in the response, so every byte takes two
+        let payload_bytes = (stack.len() * 32 + memory.as_ref().map_or(0, Bytes::len)) * 2;
+        if self.trace.steps.len() >= self.max_steps
+            || self.payload_bytes + payload_bytes > self.max_payload_bytes
+        {
+            self.trace.truncated = true;
+            return;
+        }
+        self.payload_bytes += payload_bytes;
+
+        let op = interp.current_opcode();
+        let storage = match op {
+            opcode::SSTORE if self.storage => Some(StorageAccess {
+                slot: stack.last().copied().unwrap_or_default(),
+                value: stack.iter().rev().nth(1).copied().unwrap_or_default(),
+            }),
+            // The value is only known once the opcode has executed
+            opcode::SLOAD if self.storage => Some(StorageAccess {
+                slot: stack.last().copied().unwrap_or_default(),
+                value: U256::ZERO,
+            }),
+            _ => None,
+        };
+
+        self.trace.steps.push(Step {
+            pc: interp.program_counter(),
+            op: OpCode::new(op)
+                .map(|op| op.as_str().to_string())
+                .unwrap_or_else(|| format!("0x{op:02x}")),
+            gas: interp.gas.remaining(),
+            gas_cost: 0,
+            depth: context.journaled_state.depth(),
+            stack,
+            memory,
+            storage,
+        });
+        self.recorded_op = Some(op);
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, _context: &mut EvmContext<DB>) {
+        let (Some(op), Some(step)) = (self.recorded_op, self.trace.steps.last_mut()) else {
+            return;
+        };
+
+        step.gas_cost = step.gas.saturating_sub(interp.gas.remaining());
+        if let (opcode::SLOAD, Some(storage)) = (op, &mut step.storage) {
+            storage.value = interp.stack.peek(0).unwrap_or_default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revm::db::InMemoryDB;
+    use revm::primitives::TxKind;
+    use revm::{inspector_handle_register, Evm};
+
+    /// Deploys init code that pushes and pops a word ten times, 21 steps in all.
+    fn trace(options: &StepTraceOptions) -> StepTrace {
+        let code: Bytes = [opcode::PUSH1, 0x01, opcode::POP]
+            .repeat(10)
+            .into_iter()
+            .chain([opcode::STOP])
+            .collect();
+
+        let mut tracer = StepTracer::new(options);
+        let mut evm = Evm::builder()
+            .with_db(InMemoryDB::default())
+            .with_external_context(&mut tracer)
+            .modify_tx_env(|tx| {
+                tx.transact_to = TxKind::Create;
+                tx.data = code.clone();
+            })
+            .append_handler_register(inspector_handle_register)
+            .build();
+        evm.transact().unwrap();
+        drop(evm);
+
+        tracer.into_trace()
+    }
+
+    #[test]
+    fn records_every_step() {
+        let trace = trace(&StepTraceOptions::default());
+
+        assert_eq!(trace.steps.len(), 21);
+        assert!(!trace.truncated);
+        assert_eq!(trace.steps[0].op, "PUSH1");
+        assert_eq!(trace.steps[0].gas_cost, 3);
+        assert_eq!(trace.steps[1].stack, vec![U256::from(1)]);
+        assert_eq!(trace.steps[20].op, "STOP");
+    }
+
+    #[test]
+    fn truncates_at_max_steps() {
+        let trace = trace(&StepTraceOptions {
+            max_steps: Some(5),
+            ..Default::default()
+        });
+
+        assert_eq!(trace.steps.len(), 5);
+        assert!(trace.truncated);
+    }
+
+    #[test]
+    fn truncates_at_max_payload_bytes() {
+        // Every `POP` records a single stack word, 64 bytes once hex encoded
+        let trace = trace(&StepTraceOptions {
+            max_payload_bytes: Some(128),
+            ..Default::default()
+        });
+
+        assert_eq!(trace.steps.len(), 5);
+        assert!(trace.truncated);
+    }
+
+    #[test]
+    fn caps_the_requested_limits() {
+        let tracer = StepTracer::new(&StepTraceOptions {
+            max_steps: Some(usize::MAX),
+            max_payload_bytes: Some(usize::MAX),
+            ..Default::default()
+        });
+
+        assert_eq!(tracer.max_steps, MAX_STEPS);
+        assert_eq!(tracer.max_payload_bytes, MAX_PAYLOAD_BYTES);
+    }
+}